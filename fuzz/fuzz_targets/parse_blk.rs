@@ -0,0 +1,30 @@
+#![no_main]
+
+use std::sync::{Arc, LazyLock};
+
+use libfuzzer_sys::fuzz_target;
+use wt_blk::blk::{
+	binary_deserialize::{parser::parse_blk, view::BlkView},
+	nm_file::NameMap,
+};
+
+// Decoded name map, such that SLIM files can be fuzzed as well
+static NM: LazyLock<Arc<NameMap>> = LazyLock::new(|| {
+	let binary = include_bytes!("../../samples/names").to_vec();
+	let parsed = NameMap::parse_slim_nm(&binary).unwrap();
+	Arc::new(NameMap {
+		binary,
+		parsed: Arc::new(parsed),
+	})
+});
+
+// The first byte selects between FAT (even) and SLIM (odd), the remainder is the file without its leading FileType byte
+// Regressions are kept in samples/fuzz/parse_blk, run them with `cargo fuzz run parse_blk ../samples/fuzz/parse_blk`
+fuzz_target!(|data: &[u8]| {
+	if let Some((&kind, file)) = data.split_first() {
+		let _ = parse_blk(file, kind & 1 == 1, Some(NM.clone()));
+		if let Ok(view) = BlkView::new(file, kind & 1 == 1, Some(&NM)) {
+			let _ = view.to_blk_field();
+		}
+	}
+});
@@ -1,22 +1,11 @@
-use std::{
-	any::{type_name, Any},
-	mem::size_of,
-	ops::Index,
-};
-
-use color_eyre::Report;
-
-use crate::{
-	binary_decoder::BinaryDecoderError::SeekingBackUnderflow,
-	blk::{
-		error::ParseError,
-		leb128::uleb128,
-		util::{bytes_to_int, bytes_to_uint},
-	},
-};
+use std::ops::Range;
+
+use crate::blk::{error::ParseError, leb128::uleb128};
 
-type BinaryDecoderResult<T> = Result<T, ParseError>;
+type BinaryDecoderResult<T> = Result<T, BinaryDecoderError>;
 
+/// Bounds-checked little endian reader over a byte buffer, keeping track of its position
+#[derive(Clone, Debug)]
 pub struct BinaryDecoder<'a> {
 	bytes:  &'a [u8],
 	cursor: usize,
@@ -27,82 +16,238 @@ impl<'a> BinaryDecoder<'a> {
 		Self { bytes, cursor: 0 }
 	}
 
-	/// Returns the next bytes at the current cursor
-	pub fn remaining_bytes(&self) -> BinaryDecoderResult<&'a [u8]> {
-		if self.cursor < self.bytes.len() {
-			Ok(self.bytes.index(self.cursor..))
-		} else {
-			Err(error_map(BinaryDecoderError::CursorOutOfBounds {
-				buf_len: self.bytes.len(),
-				cursor:  self.cursor,
-			}))
-		}
+	/// The entire buffer, regardless of the cursor
+	pub fn bytes(&self) -> &'a [u8] {
+		self.bytes
+	}
+
+	/// Position of the cursor, relative to the start of the buffer
+	pub fn cursor(&self) -> usize {
+		self.cursor
+	}
+
+	/// Returns the bytes following the cursor, which are empty once it reached the end
+	pub fn remaining(&self) -> &'a [u8] {
+		self.bytes.get(self.cursor..).unwrap_or_default()
 	}
 
-	/// Unchecked forwards seeking operation
-	pub fn seek(&mut self, by: usize) {
-		self.cursor += by
+	/// Checked forwards seeking operation
+	pub fn seek(&mut self, by: usize) -> BinaryDecoderResult<()> {
+		self.next_slice(by)?;
+		Ok(())
 	}
 
-	/// Checked backwards-seeking operation
+	/// Checked backwards seeking operation
 	pub fn seek_back(&mut self, by: usize) -> BinaryDecoderResult<()> {
-		self.cursor = self
-			.cursor
-			.checked_add(by)
-			.ok_or(SeekingBackUnderflow {
-				cursor:   self.cursor,
-				seekback: by,
-			})
-			.map_err(error_map)?;
+		self.cursor =
+			self.cursor
+				.checked_sub(by)
+				.ok_or(BinaryDecoderError::SeekingBackUnderflow {
+					cursor:   self.cursor,
+					seekback: by,
+				})?;
 		Ok(())
 	}
 
+	/// Moves the cursor to an absolute position, which may be the end of the buffer
+	pub fn seek_to(&mut self, offset: usize) -> BinaryDecoderResult<()> {
+		if offset > self.bytes.len() {
+			return Err(self.out_of_bounds(offset, 0));
+		}
+		self.cursor = offset;
+		Ok(())
+	}
+
+	/// Skips to the next multiple of `alignment`, unless the cursor already is aligned
+	pub fn align_to(&mut self, alignment: usize) -> BinaryDecoderResult<()> {
+		if alignment == 0 {
+			return Err(BinaryDecoderError::ZeroAlignment);
+		}
+		let padding = self.cursor.next_multiple_of(alignment) - self.cursor;
+		self.seek(padding)
+	}
+
+	/// Returns the next `len` bytes, advancing the cursor
+	pub fn next_slice(&mut self, len: usize) -> BinaryDecoderResult<&'a [u8]> {
+		let bytes = self.slice_at(self.cursor..self.cursor.saturating_add(len))?;
+		self.cursor += len;
+		Ok(bytes)
+	}
+
+	pub fn next_array<const N: usize>(&mut self) -> BinaryDecoderResult<[u8; N]> {
+		Ok(self.next_slice(N)?.try_into().expect("Infallible"))
+	}
+
+	/// Returns a decoder over the next `len` bytes, advancing the cursor past them
+	pub fn sub_decoder(&mut self, len: usize) -> BinaryDecoderResult<Self> {
+		Ok(Self::new(self.next_slice(len)?))
+	}
+
+	/// Returns a range of the buffer, without moving the cursor
+	pub fn slice_at(&self, range: Range<usize>) -> BinaryDecoderResult<&'a [u8]> {
+		self.bytes
+			.get(range.clone())
+			.ok_or_else(|| self.out_of_bounds(range.start, range.len()))
+	}
+
+	pub fn next_u8(&mut self) -> BinaryDecoderResult<u8> {
+		let [byte] = self.next_array()?;
+		Ok(byte)
+	}
+
+	pub fn next_u16(&mut self) -> BinaryDecoderResult<u16> {
+		Ok(u16::from_le_bytes(self.next_array()?))
+	}
+
+	pub fn next_u32(&mut self) -> BinaryDecoderResult<u32> {
+		Ok(u32::from_le_bytes(self.next_array()?))
+	}
+
+	pub fn next_u64(&mut self) -> BinaryDecoderResult<u64> {
+		Ok(u64::from_le_bytes(self.next_array()?))
+	}
+
+	pub fn next_f32(&mut self) -> BinaryDecoderResult<f32> {
+		Ok(f32::from_le_bytes(self.next_array()?))
+	}
+
+	/// Reads a u32 and widens it to usize, as is common for offsets and lengths
+	pub fn next_u32_usize(&mut self) -> BinaryDecoderResult<usize> {
+		Ok(self.next_u32()? as usize)
+	}
+
+	/// Reads a u64 offset or length, which has to fit into usize
+	pub fn next_u64_usize(&mut self) -> BinaryDecoderResult<usize> {
+		let cursor = self.cursor;
+		let value = self.next_u64()?;
+		usize::try_from(value).map_err(|_| BinaryDecoderError::UsizeOverflow { cursor, value })
+	}
+
 	/// Returns next uleb encoded integer, advancing the cursor
 	pub fn next_uleb(&mut self) -> BinaryDecoderResult<usize> {
-		let bytes = self.remaining_bytes()?;
-		let (uleb_len, value) = uleb128(bytes)?;
-		self.seek(uleb_len);
+		let (uleb_len, value) = uleb128(self.remaining()).map_err(|e| match e {
+			ParseError::UlebOverflow => BinaryDecoderError::UlebOverflow {
+				cursor: self.cursor,
+			},
+			// The only other errors stem from the buffer ending before the integer did
+			_ => self.out_of_bounds(self.cursor, self.remaining().len() + 1),
+		})?;
+		self.cursor += uleb_len;
 		Ok(value)
 	}
 
-	pub fn next_u32(&mut self) -> BinaryDecoderResult<u32> {
-		let bytes = self
-			.bytes
-			.get(self.cursor..(self.cursor + size_of::<u32>()))
-			.ok_or(BinaryDecoderError::CursorOutOfBounds {
-				buf_len: self.bytes.len(),
-				cursor:  self.cursor,
-			})
-			.map_err(error_map)?;
-		self.cursor += size_of::<u32>();
-		Ok(bytes_to_uint(bytes).ok_or(integer_err::<u32>(bytes))?)
+	/// Returns the bytes up to the next null byte, advancing the cursor past the null byte
+	pub fn next_cstr(&mut self) -> BinaryDecoderResult<&'a [u8]> {
+		let remaining = self.remaining();
+		let len = remaining.iter().position(|&b| b == 0).ok_or(
+			BinaryDecoderError::UnterminatedString {
+				cursor: self.cursor,
+			},
+		)?;
+		self.cursor += len + 1;
+		Ok(&remaining[..len])
 	}
-}
 
-/// Translates module local error to crate parent-module ParseError
-fn error_map(e: BinaryDecoderError) -> ParseError {
-	ParseError::BinaryDecoderError(e)
-}
-
-/// Builds error from invalid buffer and type
-fn integer_err<T: Any>(buf: &[u8]) -> ParseError {
-	ParseError::BinaryDecoderError(BinaryDecoderError::BadIntegerValue {
-		buffer:    buf.to_vec(),
-		type_name: type_name::<T>(),
-	})
+	fn out_of_bounds(&self, cursor: usize, len: usize) -> BinaryDecoderError {
+		BinaryDecoderError::CursorOutOfBounds {
+			cursor,
+			len,
+			buf_len: self.bytes.len(),
+		}
+	}
 }
 
 #[derive(Clone, thiserror::Error, Debug, PartialEq, Eq)]
 pub enum BinaryDecoderError {
-	#[error("Cursor at position {cursor} is out of bounds for {buf_len}")]
-	CursorOutOfBounds { buf_len: usize, cursor: usize },
+	#[error("Reading {len} bytes at position {cursor} is out of bounds for {buf_len} bytes")]
+	CursorOutOfBounds {
+		cursor:  usize,
+		len:     usize,
+		buf_len: usize,
+	},
 	#[error(
 		"Failed to seek backwards because seekback {seekback} was greater than cursor {cursor}"
 	)]
 	SeekingBackUnderflow { cursor: usize, seekback: usize },
-	#[error("Failed to construct {type_name} from {buffer:?}")]
-	BadIntegerValue {
-		buffer:    Vec<u8>,
-		type_name: &'static str,
-	},
+	#[error("ULEB var-int at position {cursor} has more continuation bytes than fit into a usize")]
+	UlebOverflow { cursor: usize },
+	#[error("{value} at position {cursor} does not fit into usize")]
+	UsizeOverflow { cursor: usize, value: u64 },
+	#[error("String at position {cursor} is not null terminated")]
+	UnterminatedString { cursor: usize },
+	#[error("Alignment has to be greater than zero")]
+	ZeroAlignment,
+}
+
+#[cfg(test)]
+mod test {
+	use crate::{
+		binary_decoder::{BinaryDecoder, BinaryDecoderError},
+		binary_encoder::{BinaryEncoder, BinaryEncoderError},
+	};
+
+	#[test]
+	fn round_trip() {
+		let mut encoder = BinaryEncoder::new();
+		encoder.write_u8(1);
+		encoder.write_u16(2);
+		encoder.write_u32(3);
+		encoder.write_u64(4);
+		encoder.write_f32(1.25);
+		encoder.write_uleb(300);
+		encoder.write_cstr(b"nm");
+		encoder.align_to(16).unwrap();
+		encoder.write_uleb(usize::MAX);
+		assert_eq!(encoder.len(), 32 + 10);
+
+		let buf = encoder.into_inner();
+		let mut decoder = BinaryDecoder::new(&buf);
+		assert_eq!(decoder.next_u8(), Ok(1));
+		assert_eq!(decoder.next_u16(), Ok(2));
+		assert_eq!(decoder.next_u32(), Ok(3));
+		assert_eq!(decoder.next_u64(), Ok(4));
+		assert_eq!(decoder.next_f32(), Ok(1.25));
+		assert_eq!(decoder.next_uleb(), Ok(300));
+		assert_eq!(decoder.next_cstr(), Ok(b"nm".as_slice()));
+		decoder.align_to(16).unwrap();
+		assert_eq!(decoder.next_uleb(), Ok(usize::MAX));
+		assert!(decoder.remaining().is_empty());
+	}
+
+	#[test]
+	fn bounds() {
+		let mut decoder = BinaryDecoder::new(&[1, 2, 3]);
+		assert_eq!(
+			decoder.next_u32(),
+			Err(BinaryDecoderError::CursorOutOfBounds {
+				cursor:  0,
+				len:     4,
+				buf_len: 3,
+			})
+		);
+		assert_eq!(decoder.cursor(), 0);
+		decoder.seek(2).unwrap();
+		decoder.seek_back(1).unwrap();
+		assert_eq!(decoder.next_u8(), Ok(2));
+		assert!(decoder.seek_back(3).is_err());
+		assert!(decoder.next_cstr().is_err());
+		assert!(decoder.seek_to(4).is_err());
+		assert_eq!(decoder.align_to(0), Err(BinaryDecoderError::ZeroAlignment));
+	}
+
+	#[test]
+	fn encoder_errors() {
+		let mut encoder = BinaryEncoder::new();
+		encoder.write_u32(0);
+		encoder.patch_u32(0, 7).unwrap();
+		assert_eq!(encoder.as_slice(), 7_u32.to_le_bytes());
+		assert_eq!(
+			encoder.patch_u32(1, 7),
+			Err(BinaryEncoderError::PatchOutOfBounds {
+				offset: 1,
+				len:    4,
+			})
+		);
+		assert_eq!(encoder.align_to(0), Err(BinaryEncoderError::ZeroAlignment));
+	}
 }
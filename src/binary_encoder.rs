@@ -0,0 +1,107 @@
+use std::mem::size_of;
+
+/// Growable little endian writer, the counterpart of [`crate::binary_decoder::BinaryDecoder`]
+#[derive(Clone, Debug, Default)]
+pub struct BinaryEncoder {
+	buf: Vec<u8>,
+}
+
+impl BinaryEncoder {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn with_capacity(capacity: usize) -> Self {
+		Self {
+			buf: Vec::with_capacity(capacity),
+		}
+	}
+
+	/// Amount of bytes written so far, which is the offset the next write lands at
+	pub fn len(&self) -> usize {
+		self.buf.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.buf.is_empty()
+	}
+
+	pub fn as_slice(&self) -> &[u8] {
+		&self.buf
+	}
+
+	pub fn into_inner(self) -> Vec<u8> {
+		self.buf
+	}
+
+	pub fn write_bytes(&mut self, bytes: &[u8]) {
+		self.buf.extend_from_slice(bytes);
+	}
+
+	pub fn write_u8(&mut self, value: u8) {
+		self.buf.push(value);
+	}
+
+	pub fn write_u16(&mut self, value: u16) {
+		self.write_bytes(&value.to_le_bytes());
+	}
+
+	pub fn write_u32(&mut self, value: u32) {
+		self.write_bytes(&value.to_le_bytes());
+	}
+
+	pub fn write_u64(&mut self, value: u64) {
+		self.write_bytes(&value.to_le_bytes());
+	}
+
+	pub fn write_f32(&mut self, value: f32) {
+		self.write_bytes(&value.to_le_bytes());
+	}
+
+	/// Writes the integer as ULEB, the inverse of [`crate::binary_decoder::BinaryDecoder::next_uleb`]
+	pub fn write_uleb(&mut self, mut value: usize) {
+		loop {
+			let byte = (value & 0x7F) as u8;
+			value >>= 7;
+			if value == 0 {
+				self.write_u8(byte);
+				return;
+			}
+			self.write_u8(byte | 0x80);
+		}
+	}
+
+	/// Writes the bytes followed by a null byte, the inverse of [`crate::binary_decoder::BinaryDecoder::next_cstr`]
+	pub fn write_cstr(&mut self, bytes: &[u8]) {
+		self.write_bytes(bytes);
+		self.write_u8(0);
+	}
+
+	/// Pads with null bytes up to the next multiple of `alignment`
+	pub fn align_to(&mut self, alignment: usize) -> Result<(), BinaryEncoderError> {
+		if alignment == 0 {
+			return Err(BinaryEncoderError::ZeroAlignment);
+		}
+		self.buf
+			.resize(self.buf.len().next_multiple_of(alignment), 0);
+		Ok(())
+	}
+
+	/// Overwrites a u32 that was written earlier, such as an offset that was not known at the time
+	pub fn patch_u32(&mut self, offset: usize, value: u32) -> Result<(), BinaryEncoderError> {
+		let len = self.buf.len();
+		self.buf
+			.get_mut(offset..offset.saturating_add(size_of::<u32>()))
+			.ok_or(BinaryEncoderError::PatchOutOfBounds { offset, len })?
+			.copy_from_slice(&value.to_le_bytes());
+		Ok(())
+	}
+}
+
+#[derive(Clone, thiserror::Error, Debug, PartialEq, Eq)]
+pub enum BinaryEncoderError {
+	#[error("Alignment has to be greater than zero")]
+	ZeroAlignment,
+	#[error("Patching 4 bytes at position {offset} is out of bounds for {len} written bytes")]
+	PatchOutOfBounds { offset: usize, len: usize },
+}
@@ -0,0 +1,300 @@
+use std::sync::Arc;
+
+use crate::{
+	binary_decoder::BinaryDecoder,
+	blk::{
+		binary_deserialize::view::BlkValueRef,
+		blk_block_hierarchy::{BlkBlockBuilderError, MAX_BLOCK_DEPTH},
+		blk_structure::BlkField,
+		blk_type::{blk_type_id::*, BlkString, BlkType},
+		error::{BlkSection, ErrorLocation, ParamLocation, ParseError},
+		util::blk_str,
+	},
+};
+
+const MAGIC: &[u8; 3] = b"BBF";
+const VERSION: u16 = 3;
+
+/// Parses a BBF3 file, which must not include the leading [`crate::blk::file::FileType`] byte.
+///
+/// Layout, with all integers in little endian:
+/// - Header: `BBF`, u16 version, u16 flags, u32 length of the remainder and one reserved byte
+/// - Names: count with [`next_count`], followed by u8 length-prefixed names
+/// - Strings: u24 length of the table, count and u8 length-prefixed strings
+/// - Root block: one reserved byte, u16 param count and u16 block count.
+///   All param records precede all param values, which are followed by the child blocks.
+///   Child blocks begin with the same 4 byte record as params, with a type id of 0
+pub fn parse_bbf(file: &[u8]) -> Result<BlkField, ParseError> {
+	let mut decoder = BinaryDecoder::new(file);
+
+	parse_header(&mut decoder, file.len()).map_err(at(0, BlkSection::Header))?;
+
+	let names_offset = decoder.cursor();
+	let names = Names::parse(&mut decoder).map_err(at(names_offset, BlkSection::Names))?;
+
+	let strings_offset = decoder.cursor();
+	let strings = parse_strings(&mut decoder).map_err(at(strings_offset, BlkSection::Strings))?;
+
+	let root_offset = decoder.cursor();
+	decoder
+		.next_u8()
+		.map_err(at(root_offset, BlkSection::BlockInfo))?;
+	let root = parse_block(&mut decoder, &Bbf { names, strings }, blk_str("root"), 0)?;
+
+	if decoder.cursor() != file.len() {
+		return Err(
+			ParseError::DataRegionBoundsExceeded(decoder.cursor()..file.len())
+				.at(ErrorLocation::new(decoder.cursor(), BlkSection::BlockInfo)),
+		);
+	}
+	Ok(root)
+}
+
+/// Attaches the start of the item that failed to parse to its error
+fn at<E: Into<ParseError>>(offset: usize, section: BlkSection) -> impl Fn(E) -> ParseError {
+	move |e| e.into().at(ErrorLocation::new(offset, section))
+}
+
+fn parse_header(decoder: &mut BinaryDecoder, file_len: usize) -> Result<(), ParseError> {
+	if decoder.next_slice(MAGIC.len())? != MAGIC {
+		return Err(ParseError::BadBbfMagic);
+	}
+	let version = decoder.next_u16()?;
+	if version != VERSION {
+		return Err(ParseError::UnsupportedBbfVersion { version });
+	}
+	let _flags = decoder.next_u16()?;
+	let len = decoder.next_u32()? as usize;
+	let expected = decoder.cursor()..decoder.cursor().saturating_add(len);
+	if expected.end != file_len {
+		return Err(ParseError::DataRegionBoundsExceeded(expected));
+	}
+	let _reserved = decoder.next_u8()?;
+	Ok(())
+}
+
+fn parse_strings(decoder: &mut BinaryDecoder) -> Result<Vec<BlkString>, ParseError> {
+	let _strings_len = decoder.next_array::<3>()?;
+	(0..next_count(decoder)?)
+		.map(|_| next_str(decoder))
+		.collect()
+}
+
+struct Bbf {
+	names:   Names,
+	strings: Vec<BlkString>,
+}
+
+/// Names are referred to by the low byte of their djb2 hash, and their index among names sharing that hash
+struct Names {
+	buckets: Vec<Vec<BlkString>>,
+}
+
+impl Names {
+	fn parse(decoder: &mut BinaryDecoder) -> Result<Self, ParseError> {
+		let mut buckets = vec![vec![]; 256];
+		for _ in 0..next_count(decoder)? {
+			let name = next_str(decoder)?;
+			buckets[djb2(name.as_bytes()) as usize & 0xFF].push(name);
+		}
+		Ok(Self { buckets })
+	}
+
+	fn get(&self, name_ref: u32) -> Result<BlkString, ParseError> {
+		let (hash, index) = (name_ref & 0xFF, name_ref >> 8);
+		self.buckets[hash as usize]
+			.get(index as usize)
+			.cloned()
+			.ok_or(ParseError::UnresolvedBbfName { hash, index })
+	}
+}
+
+fn parse_block(
+	decoder: &mut BinaryDecoder,
+	bbf: &Bbf,
+	name: BlkString,
+	depth: usize,
+) -> Result<BlkField, ParseError> {
+	let offset = decoder.cursor();
+	if depth >= MAX_BLOCK_DEPTH {
+		return Err(
+			ParseError::BlkBlockBuilderError(BlkBlockBuilderError::NestingTooDeep)
+				.at(ErrorLocation::new(offset, BlkSection::BlockInfo)),
+		);
+	}
+	let params_count = decoder
+		.next_u16()
+		.map_err(at(offset, BlkSection::BlockInfo))?;
+	let blocks_count = decoder
+		.next_u16()
+		.map_err(at(offset, BlkSection::BlockInfo))?;
+
+	let params = (0..params_count)
+		.map(|_| {
+			let offset = decoder.cursor();
+			next_record(decoder)
+				.map(|(name_ref, type_id)| (offset, name_ref, type_id))
+				.map_err(at(offset, BlkSection::ParamsInfo))
+		})
+		.collect::<Result<Vec<_>, _>>()?;
+
+	let mut fields = Vec::with_capacity(params.len() + blocks_count as usize);
+	for (index, (record_offset, name_ref, type_id)) in params.into_iter().enumerate() {
+		let name = bbf.names.get(name_ref);
+		let location = ErrorLocation::new(decoder.cursor(), BlkSection::ParamsData).with_param(
+			ParamLocation {
+				index,
+				name: name.clone().ok(),
+				type_id,
+			},
+		);
+		let value = next_value(decoder, bbf, type_id).map_err(|e| e.at(location))?;
+		let name = name.map_err(at(record_offset, BlkSection::ParamsInfo))?;
+		fields.push(BlkField::Value(name, value));
+	}
+
+	for _ in 0..blocks_count {
+		let record_at = at(decoder.cursor(), BlkSection::BlockInfo);
+		let (name_ref, type_id) = next_record(decoder).map_err(&record_at)?;
+		if type_id != 0 {
+			return Err(record_at(ParseError::BadBbfBlockTypeId { type_id }));
+		}
+		let name = bbf.names.get(name_ref).map_err(&record_at)?;
+		fields.push(parse_block(decoder, bbf, name, depth + 1)?);
+	}
+
+	Ok(BlkField::Struct(name, fields))
+}
+
+/// Name reference and type id of a param or block
+fn next_record(decoder: &mut BinaryDecoder) -> Result<(u32, u8), ParseError> {
+	let [a, b, c, type_id] = decoder.next_array::<4>()?;
+	Ok((u32::from_le_bytes([a, b, c, 0]), type_id))
+}
+
+fn next_value(decoder: &mut BinaryDecoder, bbf: &Bbf, type_id: u8) -> Result<BlkType, ParseError> {
+	let len = match type_id {
+		STRING => {
+			let index = decoder.next_u16()? as usize;
+			return bbf.strings.get(index).cloned().map(BlkType::Str).ok_or(
+				ParseError::BbfStringIndexOutOfBounds {
+					index,
+					len: bbf.strings.len(),
+				},
+			);
+		},
+		BOOL => {
+			return Ok(BlkType::Bool(decoder.next_u16()? != 0));
+		},
+		COLOR => {
+			let [b, g, r, a] = decoder.next_array::<4>()?;
+			return Ok(BlkType::Color { r, g, b, a });
+		},
+		INT | FLOAT => 4,
+		INT2 | FLOAT2 | LONG => 8,
+		INT3 | FLOAT3 => 12,
+		FLOAT4 => 16,
+		FLOAT12 => 48,
+		_ => return Err(ParseError::BadBlkValue),
+	};
+	// Remaining types are stored as they are in FAT files, either in the 4 byte field or in the data region
+	let data = decoder.next_slice(len)?;
+	let value = if len == 4 {
+		BlkValueRef::from_raw_param_info(type_id, data, &[])
+	} else {
+		BlkValueRef::from_raw_param_info(type_id, &0_u32.to_le_bytes(), data)
+	};
+	value.map(BlkType::from).ok_or(ParseError::BadBlkValue)
+}
+
+/// Counts are prefixed with a byte whose upper two bits encode the width of the count
+fn next_count(decoder: &mut BinaryDecoder) -> Result<usize, ParseError> {
+	let prefix = decoder.next_u8()?;
+	Ok(match prefix >> 6 {
+		1 => decoder.next_u8()? as usize,
+		2 => decoder.next_u16()? as usize,
+		3 => decoder.next_u32()? as usize,
+		_ => return Err(ParseError::BadBbfCountWidth { prefix }),
+	})
+}
+
+fn next_str(decoder: &mut BinaryDecoder) -> Result<BlkString, ParseError> {
+	let len = decoder.next_u8()?;
+	let bytes = decoder.next_slice(len as usize)?;
+	Ok(Arc::from(String::from_utf8_lossy(bytes).to_string()))
+}
+
+fn djb2(bytes: &[u8]) -> u32 {
+	bytes.iter().fold(5381_u32, |hash, &byte| {
+		hash.wrapping_mul(33).wrapping_add(byte as u32)
+	})
+}
+
+#[cfg(test)]
+mod test {
+	use std::fs;
+
+	use crate::blk::{
+		binary_deserialize::bbf::parse_bbf,
+		blk_structure::BlkField,
+		blk_type::{blk_type_id::STRING, BlkType},
+		error::{BlkSection, ErrorLocation, ParamLocation, ParseError},
+		make_strict_test,
+		unpack_blk,
+		util::blk_str,
+	};
+
+	#[test]
+	fn bbf_sample() {
+		let file = fs::read("./samples/section_bbf.blk").unwrap();
+		let output = parse_bbf(&file[1..]).unwrap();
+
+		// Unlike the other samples of this structure, "bool" is false here:
+		// its value is the u16 `00 00` at byte 0xAE of section_bbf.blk, following the string index of "str" at 0xAC
+		let mut expected = make_strict_test();
+		let BlkField::Struct(_, fields) = &mut expected else {
+			unreachable!()
+		};
+		let BlkField::Struct(_, alpha) = &mut fields[3] else {
+			unreachable!()
+		};
+		alpha[1] = BlkField::Value(alpha[1].get_name(), BlkType::Bool(false));
+		assert_eq!(expected, output);
+		assert_eq!(expected, unpack_blk(&mut file.clone(), None, None).unwrap());
+	}
+
+	#[test]
+	fn bad_count_width() {
+		let mut file = fs::read("./samples/section_bbf.blk").unwrap();
+		// Prefix of the name count
+		file[13] = 0x00;
+		let err = parse_bbf(&file[1..]).unwrap_err();
+		assert_eq!(err.kind(), &ParseError::BadBbfCountWidth { prefix: 0 });
+		assert_eq!(
+			err.location(),
+			Some(&ErrorLocation::new(12, BlkSection::Names))
+		);
+	}
+
+	#[test]
+	fn string_index_out_of_bounds() {
+		let mut file = fs::read("./samples/section_bbf.blk").unwrap();
+		// String index of alpha's "str", the table only holds "hello"
+		file[0xAC] = 5;
+		let err = parse_bbf(&file[1..]).unwrap_err();
+		assert_eq!(
+			err.kind(),
+			&ParseError::BbfStringIndexOutOfBounds { index: 5, len: 1 }
+		);
+		assert_eq!(
+			err.location(),
+			Some(
+				&ErrorLocation::new(0xAB, BlkSection::ParamsData).with_param(ParamLocation {
+					index:   0,
+					name:    Some(blk_str("str")),
+					type_id: STRING,
+				})
+			)
+		);
+	}
+}
@@ -0,0 +1,257 @@
+use std::borrow::Cow;
+
+use fallible_iterator::FallibleIterator;
+use tracing::error;
+
+use crate::blk::{
+	binary_deserialize::{
+		parser::{index_blocks, split_param_info, BlkSections, BlockInfo, ParseOptions},
+		view::BlkValueRef,
+	},
+	blk_block_hierarchy::{BlkBlockBuilderError, MAX_BLOCK_DEPTH},
+	blk_type::{
+		blk_type_id::{is_known, STRING},
+		BlkString,
+		BlkType,
+	},
+	error::{BlkSection, ErrorLocation, ParamLocation, ParseError},
+	nm_file::NameMap,
+	util::blk_str,
+};
+
+/// Element of a BLK, as emitted in document order by [`BlkEventReader`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlkEvent {
+	/// Start of a block, the first event always begins the root
+	BeginBlock(BlkString),
+	/// Parameter of the most recently begun block, which precede its child blocks
+	Value(BlkString, BlkType),
+	/// End of the most recently begun block
+	EndBlock,
+}
+
+/// Pull reader yielding the events of a FAT or SLIM BLK, without building a tree
+pub struct BlkEventReader<'a> {
+	names:              Cow<'a, [BlkString]>,
+	params_data:        &'a [u8],
+	params_info:        &'a [u8],
+	/// Offset of the params info section, such that errors can point at the bad record
+	params_info_offset: usize,
+	/// Region strings are read from, SLIM files store them in the name map
+	strings:            &'a [u8],
+	blocks:             Vec<BlockInfo>,
+	/// Blocks that have begun but not yet ended
+	open:               Vec<Cursor>,
+	started:            bool,
+	options:            ParseOptions,
+}
+
+/// Next parameter and child to be emitted for an open block
+struct Cursor {
+	block:      usize,
+	next_param: usize,
+	next_block: usize,
+}
+
+impl<'a> BlkEventReader<'a> {
+	/// Indexes the file, which must not include the leading [`crate::blk::file::FileType`] byte
+	pub fn new(
+		file: &'a [u8],
+		is_slim: bool,
+		shared_name_map: Option<&'a NameMap>,
+	) -> Result<Self, ParseError> {
+		Self::new_with_options(file, is_slim, shared_name_map, ParseOptions::default())
+	}
+
+	/// Same as [`BlkEventReader::new`], with custom parsing options
+	pub fn new_with_options(
+		file: &'a [u8],
+		is_slim: bool,
+		shared_name_map: Option<&'a NameMap>,
+		options: ParseOptions,
+	) -> Result<Self, ParseError> {
+		let sections = BlkSections::split(file, is_slim)?;
+
+		let (names, strings) = match sections.names_data {
+			None => {
+				// TODO Figure out if names_count dictates the existence of a name map or if it may be 0 without requiring a name map
+				let nm = shared_name_map.ok_or(ParseError::SlimBlkWithoutNm)?;
+				(Cow::Borrowed(nm.parsed.as_slice()), nm.binary.as_slice())
+			},
+			Some(names_data) => {
+				let names = match &options.interner {
+					Some(interner) => {
+						NameMap::parse_name_section_with(names_data, |name| interner.intern(name))
+					},
+					None => NameMap::parse_name_section(names_data)?,
+				};
+				if sections.names_count != names.len() {
+					error!("Name count mismatch, expected {}, but found a len of {}. This might mean something is wrong.", sections.names_count, names.len());
+				}
+				(Cow::Owned(names), sections.params_data)
+			},
+		};
+
+		let blocks = index_blocks(&sections, names.len())?;
+
+		Ok(Self {
+			names,
+			params_data: sections.params_data,
+			params_info: sections.params_info,
+			params_info_offset: sections.offset_of(sections.params_info),
+			strings,
+			blocks,
+			open: vec![],
+			started: false,
+			options,
+		})
+	}
+
+	fn begin_block(&mut self, block: usize) -> Result<BlkEvent, ParseError> {
+		if self.open.len() >= MAX_BLOCK_DEPTH {
+			return Err(ParseError::BlkBlockBuilderError(
+				BlkBlockBuilderError::NestingTooDeep,
+			));
+		}
+		let info = self.blocks[block];
+		self.open.push(Cursor {
+			block,
+			next_param: info.first_param,
+			next_block: info.first_block,
+		});
+		let name = match info.name_id {
+			0 => match &self.options.interner {
+				Some(interner) => interner.intern("root"),
+				None => blk_str("root"),
+			},
+			id => self.name(id - 1)?,
+		};
+		Ok(BlkEvent::BeginBlock(name))
+	}
+
+	fn value(&self, index: usize) -> Result<BlkEvent, ParseError> {
+		let location =
+			ErrorLocation::new(self.params_info_offset + index * 8, BlkSection::ParamsInfo);
+		let chunk = self
+			.params_info
+			.get(index * 8..index * 8 + 8)
+			.and_then(|chunk| chunk.try_into().ok())
+			.ok_or_else(|| ParseError::BadBlkValue.at(location.clone()))?;
+		let (name_id, type_id, data) = split_param_info(chunk);
+		let data_region = if type_id == STRING {
+			self.strings
+		} else {
+			self.params_data
+		};
+		let value = BlkType::from_raw_param_info_interned(
+			type_id,
+			data,
+			data_region,
+			&self.names,
+			self.options.interner.as_deref(),
+		)
+		.or_else(|| {
+			if !self.options.keep_unknown_types || is_known(type_id) {
+				return None;
+			}
+			BlkValueRef::unknown(
+				type_id,
+				data,
+				self.params_info,
+				self.params_data,
+				self.strings,
+			)
+			.map(BlkType::from)
+		})
+		.ok_or_else(|| {
+			ParseError::BadBlkValue.at(location.with_param(ParamLocation {
+				index,
+				name: self.names.get(name_id).cloned(),
+				type_id,
+			}))
+		})?;
+		Ok(BlkEvent::Value(self.name(name_id)?, value))
+	}
+
+	fn name(&self, index: usize) -> Result<BlkString, ParseError> {
+		self.names
+			.get(index)
+			.cloned()
+			.ok_or(ParseError::NameIndexOutOfBounds {
+				index,
+				len: self.names.len(),
+			})
+	}
+}
+
+impl FallibleIterator for BlkEventReader<'_> {
+	type Error = ParseError;
+	type Item = BlkEvent;
+
+	fn next(&mut self) -> Result<Option<Self::Item>, Self::Error> {
+		if !self.started {
+			self.started = true;
+			return self.begin_block(0).map(Some);
+		}
+
+		let Some(cursor) = self.open.last_mut() else {
+			return Ok(None);
+		};
+		let info = self.blocks[cursor.block];
+		if info.params().contains(&cursor.next_param) {
+			let index = cursor.next_param;
+			cursor.next_param += 1;
+			self.value(index).map(Some)
+		} else if info.blocks().contains(&cursor.next_block) {
+			let block = cursor.next_block;
+			cursor.next_block += 1;
+			self.begin_block(block).map(Some)
+		} else {
+			self.open.pop();
+			Ok(Some(BlkEvent::EndBlock))
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::fs;
+
+	use fallible_iterator::FallibleIterator;
+
+	use crate::blk::{
+		binary_deserialize::events::{BlkEvent, BlkEventReader},
+		blk_type::BlkType,
+		util::blk_str,
+	};
+
+	#[test]
+	fn document_order() {
+		let file = fs::read("./samples/section_fat.blk").unwrap();
+		let events: Vec<_> = BlkEventReader::new(&file[1..], false, None)
+			.unwrap()
+			.collect()
+			.unwrap();
+
+		assert_eq!(events.first(), Some(&BlkEvent::BeginBlock(blk_str("root"))));
+		assert_eq!(events.last(), Some(&BlkEvent::EndBlock));
+		assert_eq!(
+			events
+				.iter()
+				.filter(|e| matches!(e, BlkEvent::BeginBlock(_)))
+				.count(),
+			events
+				.iter()
+				.filter(|e| matches!(e, BlkEvent::EndBlock))
+				.count()
+		);
+		let gamma = events
+			.iter()
+			.position(|e| e == &BlkEvent::BeginBlock(blk_str("gamma")))
+			.unwrap();
+		assert_eq!(
+			events[gamma + 1],
+			BlkEvent::Value(blk_str("vec2i"), BlkType::Int2([3, 4]))
+		);
+	}
+}
@@ -0,0 +1,335 @@
+use std::{
+	borrow::Cow,
+	fmt::{Display, Formatter},
+	ops::Range,
+};
+
+use crate::blk::{
+	binary_deserialize::parser::{raw_blocks, split_param_info, BlkSections},
+	blk_type::{blk_type_id::STRING, BlkString, BlkType},
+	error::ParseError,
+	leb128::uleb128,
+	nm_file::NameMap,
+};
+
+/// Raw records of a FAT or SLIM BLK as they are stored, without checking that they form a valid tree.
+/// Its [`Display`] implementation is a hex-annotated dump of the file
+pub struct BlkLayout<'a> {
+	file:              &'a [u8],
+	/// Names referred to by params and blocks, empty for SLIM files without a name map
+	names:             Cow<'a, [BlkString]>,
+	/// Region strings are read from, unknown for SLIM files without a name map
+	strings:           Option<&'a [u8]>,
+	/// ULEB encoded counts and sizes preceding the sections
+	pub header:        Vec<HeaderField>,
+	/// Only FAT files carry their own names
+	pub names_section: Option<Range<usize>>,
+	pub params_data:   Range<usize>,
+	pub params:        Vec<ParamRecord>,
+	pub blocks:        Vec<BlockRecord>,
+	/// Why decoding the block records stopped early, if it did
+	pub blocks_error:  Option<ParseError>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct HeaderField {
+	pub offset: usize,
+	pub len:    usize,
+	pub label:  &'static str,
+	pub value:  usize,
+}
+
+/// 8 byte param info record
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ParamRecord {
+	pub offset:  usize,
+	pub name_id: usize,
+	pub type_id: u8,
+	/// Either the value itself, or its offset into the params data
+	pub field:   [u8; 4],
+}
+
+/// Block info record, whose fields are ULEB encoded
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BlockRecord {
+	pub offset:       usize,
+	pub len:          usize,
+	/// Index into the names offset by one, as 0 refers to the root
+	pub name_id:      usize,
+	pub param_count:  usize,
+	pub blocks_count: usize,
+	pub first_block:  Option<usize>,
+}
+
+impl<'a> BlkLayout<'a> {
+	/// Splits the file, which must not include the leading [`crate::blk::file::FileType`] byte.
+	/// The name map is optional and only used to annotate names in SLIM files
+	pub fn new(
+		file: &'a [u8],
+		is_slim: bool,
+		shared_name_map: Option<&'a NameMap>,
+	) -> Result<Self, ParseError> {
+		let sections = BlkSections::split(file, is_slim)?;
+
+		let (names, strings) = match (sections.names_data, shared_name_map) {
+			(Some(names_data), _) => (
+				Cow::Owned(NameMap::parse_name_section(names_data)?),
+				Some(sections.params_data),
+			),
+			(None, Some(nm)) => (
+				Cow::Borrowed(nm.parsed.as_slice()),
+				Some(nm.binary.as_slice()),
+			),
+			(None, None) => (Cow::Borrowed([].as_slice()), None),
+		};
+
+		let params_data = sections.offset_of(sections.params_data);
+		let mut header = match sections.names_data {
+			Some(names_data) => header_fields(file, 0, &["names count", "names size"])
+				.chain(header_fields(
+					file,
+					sections.offset_of(names_data) + names_data.len(),
+					&["blocks count", "params count", "params data size"],
+				))
+				.collect::<Result<Vec<_>, _>>()?,
+			None => header_fields(
+				file,
+				0,
+				&[
+					"names count",
+					"blocks count",
+					"params count",
+					"params data size",
+				],
+			)
+			.collect::<Result<Vec<_>, _>>()?,
+		};
+		header.sort_by_key(|field| field.offset);
+
+		let params_info = sections.offset_of(sections.params_info);
+		let params = sections
+			.params_info
+			.chunks_exact(8)
+			.enumerate()
+			.map(|(i, chunk)| {
+				let (name_id, type_id, field) =
+					split_param_info(chunk.try_into().expect("Infallible"));
+				ParamRecord {
+					offset: params_info + i * 8,
+					name_id,
+					type_id,
+					field: field.try_into().expect("Infallible"),
+				}
+			})
+			.collect();
+
+		let mut blocks = vec![];
+		let mut blocks_error = None;
+		for block in raw_blocks(&sections) {
+			match block {
+				Ok(block) => blocks.push(BlockRecord {
+					offset:       block.offset,
+					len:          block.len,
+					name_id:      block.name_id,
+					param_count:  block.param_count,
+					blocks_count: block.blocks_count,
+					first_block:  block.first_block,
+				}),
+				Err(e) => {
+					blocks_error = Some(e);
+					break;
+				},
+			}
+		}
+
+		Ok(Self {
+			file,
+			names,
+			strings,
+			header,
+			names_section: sections
+				.names_data
+				.map(|data| sections.offset_of(data)..sections.offset_of(data) + data.len()),
+			params_data: params_data..params_data + sections.params_data.len(),
+			params,
+			blocks,
+			blocks_error,
+		})
+	}
+
+	/// Name a param or block refers to, if it can be resolved
+	pub fn name(&self, index: usize) -> Option<&BlkString> {
+		self.names.get(index)
+	}
+
+	/// Decodes the value of a param record, if it is valid
+	pub fn value(&self, param: &ParamRecord) -> Option<BlkType> {
+		let data_region = if param.type_id == STRING {
+			self.strings?
+		} else {
+			&self.file[self.params_data.clone()]
+		};
+		BlkType::from_raw_param_info(param.type_id, &param.field, data_region, &self.names)
+	}
+
+	fn fmt_header_in(&self, f: &mut Formatter<'_>, range: Range<usize>) -> std::fmt::Result {
+		for field in self
+			.header
+			.iter()
+			.filter(|field| range.contains(&field.offset))
+		{
+			write_row(
+				f,
+				field.offset,
+				&self.file[field.offset..field.offset + field.len],
+			)?;
+			writeln!(f, "{} = {}", field.label, field.value)?;
+		}
+		Ok(())
+	}
+}
+
+impl Display for BlkLayout<'_> {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		let names_start = self
+			.names_section
+			.as_ref()
+			.map_or(self.params_data.start, |names| names.start);
+		writeln!(f, "header")?;
+		self.fmt_header_in(f, 0..names_start)?;
+
+		if let Some(names) = &self.names_section {
+			writeln!(f, "names {}", fmt_range(names))?;
+			let mut offset = names.start;
+			for (i, name) in self.file[names.clone()]
+				.split_inclusive(|&b| b == 0)
+				.enumerate()
+			{
+				write_row(f, offset, name)?;
+				writeln!(
+					f,
+					"#{i} {:?}",
+					String::from_utf8_lossy(name.strip_suffix(&[0]).unwrap_or(name))
+				)?;
+				offset += name.len();
+			}
+			writeln!(f, "header")?;
+			self.fmt_header_in(f, names.end..self.params_data.start)?;
+		}
+
+		writeln!(f, "params data {}", fmt_range(&self.params_data))?;
+		for (i, chunk) in self.file[self.params_data.clone()].chunks(8).enumerate() {
+			write_row(f, self.params_data.start + i * 8, chunk)?;
+			writeln!(f)?;
+		}
+
+		writeln!(f, "params info ({} records)", self.params.len())?;
+		for (i, param) in self.params.iter().enumerate() {
+			write_row(f, param.offset, &self.file[param.offset..param.offset + 8])?;
+			write!(f, "#{i} name {}", param.name_id)?;
+			if let Some(name) = self.name(param.name_id) {
+				write!(f, " {name:?}")?;
+			}
+			write!(f, ", type {:#04X}", param.type_id)?;
+			match self.value(param) {
+				Some(value) => writeln!(f, ", {value}")?,
+				None => writeln!(f, ", undecodable")?,
+			}
+		}
+
+		writeln!(f, "block info ({} records)", self.blocks.len())?;
+		for (i, block) in self.blocks.iter().enumerate() {
+			write_row(
+				f,
+				block.offset,
+				&self.file[block.offset..block.offset + block.len],
+			)?;
+			match block.name_id {
+				0 => write!(f, "#{i} root")?,
+				id => {
+					write!(f, "#{i} name {}", id - 1)?;
+					if let Some(name) = self.name(id - 1) {
+						write!(f, " {name:?}")?;
+					}
+				},
+			}
+			write!(
+				f,
+				", {} params, {} blocks",
+				block.param_count, block.blocks_count
+			)?;
+			match block.first_block {
+				Some(first) => writeln!(f, ", first block #{first}")?,
+				None => writeln!(f)?,
+			}
+		}
+		if let Some(e) = &self.blocks_error {
+			writeln!(f, "block info ends early: {e}")?;
+		}
+		Ok(())
+	}
+}
+
+/// Decodes consecutive ULEB integers starting at `offset`, one for each label
+fn header_fields<'a>(
+	file: &'a [u8],
+	mut offset: usize,
+	labels: &'a [&'static str],
+) -> impl Iterator<Item = Result<HeaderField, ParseError>> + 'a {
+	labels.iter().map(move |&label| {
+		let (len, value) = uleb128(file.get(offset..).unwrap_or_default())?;
+		let field = HeaderField {
+			offset,
+			len,
+			label,
+			value,
+		};
+		offset += len;
+		Ok(field)
+	})
+}
+
+/// Writes the offset and bytes of a row, padded such that annotations of up to 8 bytes align
+fn write_row(f: &mut Formatter<'_>, offset: usize, bytes: &[u8]) -> std::fmt::Result {
+	write!(f, "  {offset:08X}  ")?;
+	let mut width = 0;
+	for byte in bytes {
+		write!(f, "{byte:02X} ")?;
+		width += 3;
+	}
+	write!(f, "{:1$} ", "", (8 * 3_usize).saturating_sub(width))
+}
+
+fn fmt_range(range: &Range<usize>) -> String {
+	format!(
+		"{:08X}..{:08X} ({} bytes)",
+		range.start,
+		range.end,
+		range.len()
+	)
+}
+
+#[cfg(test)]
+mod test {
+	use std::fs;
+
+	use crate::blk::binary_deserialize::layout::BlkLayout;
+
+	#[test]
+	fn fat_layout() {
+		let file = fs::read("./samples/section_fat.blk").unwrap();
+		let layout = BlkLayout::new(&file[1..], false, None).unwrap();
+
+		assert_eq!(layout.header[0].value, 14);
+		assert_eq!(layout.params.len(), 12);
+		assert_eq!(layout.blocks.len(), 4);
+		assert_eq!(layout.blocks[0].name_id, 0);
+		assert_eq!(layout.blocks[0].first_block, Some(1));
+		assert!(layout.blocks_error.is_none());
+
+		let dump = layout.to_string();
+		assert!(dump.contains("#0 \"vec4f\""));
+		assert!(dump.contains("#0 root, 3 params, 2 blocks, first block #1"));
+		assert!(dump.contains("#1 name 1 \"int\", type 0x02, i = 42"));
+	}
+}
@@ -1,2 +1,14 @@
 /// Exports core function for unpacking BLK file
 pub mod parser;
+
+/// Zero-copy view over a BLK file that decodes values only when they are accessed
+pub mod view;
+
+/// Event-based reader emitting the blocks and values of a BLK file in document order
+pub mod events;
+
+/// Reader for the BBF3 container, which predates the FAT and SLIM formats
+pub mod bbf;
+
+/// Hex-annotated view of the raw sections and records of a BLK file, for inspecting malformed files
+pub mod layout;
@@ -1,198 +1,353 @@
-use std::{borrow::Cow, sync::Arc};
+use std::{ops::Range, sync::Arc};
 
-use tracing::error;
+use fallible_iterator::FallibleIterator;
 
-use crate::blk::{
-	blk_block_hierarchy::FlatBlock,
-	blk_structure::BlkField,
-	blk_type::{blk_type_id::STRING, BlkType},
-	error::{
-		ParseError,
-		ParseError::{BadBlkValue, ResidualBlockBuffer},
+use crate::{
+	binary_decoder::{BinaryDecoder, BinaryDecoderError},
+	blk::{
+		binary_deserialize::events::{BlkEvent, BlkEventReader},
+		blk_block_hierarchy::BlkBlockBuilderError,
+		blk_structure::BlkField,
+		error::{BlkSection, ErrorLocation, ParamLocation, ParseError},
+		interner::StringInterner,
+		nm_file::NameMap,
 	},
-	leb128::uleb128,
-	nm_file::NameMap,
-	util::blk_str,
 };
 
+/// Options that control how binary BLK files are parsed
+#[derive(Clone, Debug, Default)]
+pub struct ParseOptions {
+	/// Keeps params of unknown types as [`crate::blk::blk_type::BlkType::Unknown`], instead of failing the entire file
+	pub keep_unknown_types: bool,
+	/// Shares names and strings with all other files parsed with the same interner
+	pub interner:           Option<Arc<StringInterner>>,
+}
+
 /// Lowest-level function which unpacks BLK to [`crate::blk::blk_structure::BlkField`]
 pub fn parse_blk(
 	file: &[u8],
 	is_slim: bool,
 	shared_name_map: Option<Arc<NameMap>>,
 ) -> Result<BlkField, ParseError> {
-	let mut ptr = 0;
-
-	// Globally increments ptr and returns next uleb integer from file
-	let next_uleb = |ptr: &mut usize| {
-		// Using ? inside of closures is not supported yet, so we need to use this match
-		match uleb128(&file[*ptr..]) {
-			Ok((offset, int)) => {
-				*ptr += offset;
-				Ok(int)
+	parse_blk_with_options(file, is_slim, shared_name_map, ParseOptions::default())
+}
+
+/// Same as [`parse_blk`], with custom parsing options
+pub fn parse_blk_with_options(
+	file: &[u8],
+	is_slim: bool,
+	shared_name_map: Option<Arc<NameMap>>,
+	options: ParseOptions,
+) -> Result<BlkField, ParseError> {
+	let mut events =
+		BlkEventReader::new_with_options(file, is_slim, shared_name_map.as_deref(), options)?;
+
+	// Blocks that have begun but not yet ended, the root being the first
+	let mut open: Vec<BlkField> = vec![];
+	while let Some(event) = events.next()? {
+		match event {
+			BlkEvent::BeginBlock(name) => open.push(BlkField::new_struct(name)),
+			BlkEvent::Value(name, value) => {
+				open.last_mut()
+					.and_then(|block| block.insert_field(BlkField::Value(name, value)))
+					.ok_or(ParseError::BlkBlockBuilderError(
+						BlkBlockBuilderError::InsertingIntoNonStruct,
+					))?;
+			},
+			BlkEvent::EndBlock => {
+				let block = open.pop().ok_or(ParseError::BlkBlockBuilderError(
+					BlkBlockBuilderError::InitialElementMissing,
+				))?;
+				match open.last_mut() {
+					Some(parent) => {
+						parent
+							.insert_field(block)
+							.ok_or(ParseError::BlkBlockBuilderError(
+								BlkBlockBuilderError::InsertingIntoNonStruct,
+							))?
+					},
+					None => return Ok(block),
+				}
 			},
-			Err(e) => Err(e),
 		}
-	};
+	}
+	Err(ParseError::BlkBlockBuilderError(
+		BlkBlockBuilderError::InitialElementMissing,
+	))
+}
 
-	// Returns slice offset from file, incrementing the ptr by offset
-	let idx_file_offset = |ptr: &mut usize, offset: usize| {
-		let res = file
-			.get(*ptr..(*ptr + offset))
-			.ok_or(ParseError::DataRegionBoundsExceeded(*ptr..(*ptr + offset)));
-		*ptr += offset;
-		res
-	};
+/// Regions of a binary BLK, as they are laid out in the file
+pub(crate) struct BlkSections<'a> {
+	pub file:         &'a [u8],
+	pub names_count:  usize,
+	/// Only FAT files carry their own names, SLIM files refer to the shared name map
+	pub names_data:   Option<&'a [u8]>,
+	pub blocks_count: usize,
+	pub params_count: usize,
+	pub params_data:  &'a [u8],
+	/// One 8 byte record per parameter
+	pub params_info:  &'a [u8],
+	pub block_info:   &'a [u8],
+}
 
-	let names_count = next_uleb(&mut ptr)?;
+impl<'a> BlkSections<'a> {
+	pub(crate) fn split(file: &'a [u8], is_slim: bool) -> Result<Self, ParseError> {
+		let mut decoder = BinaryDecoder::new(file);
+		// Attaches the location the decoder was at before the read to its error
+		let at = |cursor: usize, section: BlkSection| {
+			move |e: BinaryDecoderError| ParseError::from(e).at(ErrorLocation::new(cursor, section))
+		};
 
-	let names = if is_slim {
-		// TODO Figure out if names_count dictates the existence of a name map or if it may be 0 without requiring a name map
-		Cow::Borrowed(
-			shared_name_map
-				.as_deref()
-				.ok_or(ParseError::SlimBlkWithoutNm)?
-				.parsed
-				.as_ref(),
-		)
-	} else {
-		let names_data_size = next_uleb(&mut ptr)?;
+		let names_count = decoder
+			.next_uleb()
+			.map_err(at(decoder.cursor(), BlkSection::Header))?;
 
-		let names = NameMap::parse_name_section(idx_file_offset(&mut ptr, names_data_size)?)?;
-		if names_count != names.len() {
-			error!("Name count mismatch, expected {names_count}, but found a len of {}. This might mean something is wrong.", names.len());
-		}
-		Cow::Owned(names)
-	};
-
-	let blocks_count = next_uleb(&mut ptr)?;
-
-	let params_count = next_uleb(&mut ptr)?;
-
-	let params_data_size = next_uleb(&mut ptr)?;
-
-	let params_data = idx_file_offset(&mut ptr, params_data_size)?;
-
-	let params_info = idx_file_offset(&mut ptr, params_count * 8)?;
-
-	let block_info = &file.get(ptr..).ok_or(ResidualBlockBuffer)?;
-
-	let _ptr = (); // Shadowing ptr causes it to become unusable, especially on accident
-
-	let mut results: Vec<(usize, Option<BlkField>)> = Vec::with_capacity(params_info.len() / 8);
-
-	let chunks = params_info.array_chunks::<8>();
-	if chunks.remainder().len() != 0 {
-		error!("Params info chunks did not align to 8 bytes")
-	} // TODO: Decide whether or not this constitutes a hard crash
-	for chunk in chunks {
-		let name_id_raw = &chunk[0..3];
-		let name_id = u32::from_le_bytes([name_id_raw[0], name_id_raw[1], name_id_raw[2], 0]);
-		let type_id = chunk[3];
-		let data = &chunk[4..];
-		let name = names
-			.get(name_id as usize)
-			.ok_or(ParseError::Custom(format!(
-				"Name index {} out of bounds for name map of length {}",
-				name_id,
-				names.len()
-			)))?
-			.clone();
-
-		let parsed = if is_slim && type_id == STRING {
-			BlkType::from_raw_param_info(
-				type_id,
-				data,
-				shared_name_map
-					.as_deref()
-					.ok_or(ParseError::SlimBlkWithoutNm)?
-					.binary
-					.as_slice(),
-				shared_name_map
-					.as_deref()
-					.ok_or(ParseError::SlimBlkWithoutNm)?
-					.parsed
-					.as_slice(),
-			)
-			.ok_or(BadBlkValue)?
+		let names_data = if is_slim {
+			None
 		} else {
-			BlkType::from_raw_param_info(type_id, data, params_data, names.as_ref())
-				.ok_or(BadBlkValue)?
+			let names_data_size = decoder
+				.next_uleb()
+				.map_err(at(decoder.cursor(), BlkSection::Header))?;
+			Some(
+				decoder
+					.next_slice(names_data_size)
+					.map_err(at(decoder.cursor(), BlkSection::Names))?,
+			)
 		};
 
-		let field = BlkField::Value(name, parsed);
-		results.push((name_id as usize, Some(field)));
+		let blocks_count = decoder
+			.next_uleb()
+			.map_err(at(decoder.cursor(), BlkSection::Header))?;
+
+		let params_count = decoder
+			.next_uleb()
+			.map_err(at(decoder.cursor(), BlkSection::Header))?;
+
+		let params_data_size = decoder
+			.next_uleb()
+			.map_err(at(decoder.cursor(), BlkSection::Header))?;
+
+		let params_data = decoder
+			.next_slice(params_data_size)
+			.map_err(at(decoder.cursor(), BlkSection::ParamsData))?;
+
+		let params_info = decoder
+			.next_slice(params_count.saturating_mul(8))
+			.map_err(at(decoder.cursor(), BlkSection::ParamsInfo))?;
+
+		let block_info = decoder.remaining();
+
+		Ok(Self {
+			file,
+			names_count,
+			names_data,
+			blocks_count,
+			params_count,
+			params_data,
+			params_info,
+			block_info,
+		})
 	}
 
-	let mut block_ptr = 0;
-	let block_id_to_name = |id| {
-		if id == 0 {
-			blk_str("root")
-		} else {
-			(names)[(id - 1) as usize].clone()
-		}
+	/// Offset of a slice of this file, relative to its start
+	pub(crate) fn offset_of(&self, section: &[u8]) -> usize {
+		section.as_ptr() as usize - self.file.as_ptr() as usize
+	}
+
+	/// Location of the params info record at `index`
+	pub(crate) fn param_location(&self, index: usize) -> ErrorLocation {
+		ErrorLocation::new(
+			self.offset_of(self.params_info) + index * 8,
+			BlkSection::ParamsInfo,
+		)
+	}
+}
+
+/// Splits a parameter record into its name id, type id and 4 byte value field
+pub(crate) fn split_param_info(chunk: &[u8; 8]) -> (usize, u8, &[u8]) {
+	let name_id = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], 0]) as usize;
+	(name_id, chunk[3], &chunk[4..])
+}
+
+/// Block record as stored in the block info section
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct RawBlock {
+	/// Location of the record in the file
+	pub offset:       usize,
+	/// Length of the record in bytes, as its fields are ULEB encoded
+	pub len:          usize,
+	/// Index into the names offset by one, as 0 refers to the root
+	pub name_id:      usize,
+	/// Amount of non-block fields
+	pub param_count:  usize,
+	/// Amount of child-blocks
+	pub blocks_count: usize,
+	/// If it has child-blocks, starting index of said block
+	pub first_block:  Option<usize>,
+}
+
+/// Decodes all consecutive block records
+pub(crate) fn raw_blocks<'a>(
+	sections: &BlkSections<'a>,
+) -> impl Iterator<Item = Result<RawBlock, ParseError>> + 'a {
+	let block_info = sections.block_info;
+	let base = sections.offset_of(block_info);
+	let mut decoder = BinaryDecoder::new(block_info);
+	// Yields the location of the integer in the file, and the integer itself
+	let mut next_block_uleb = move || {
+		let start = base + decoder.cursor();
+		let int = decoder.next_uleb().map_err(|e| {
+			ParseError::from(e).at(ErrorLocation::new(start, BlkSection::BlockInfo))
+		})?;
+		Ok::<_, ParseError>((start..base + decoder.cursor(), int))
 	};
-	let blocks = {
-		(0..blocks_count).into_iter().map(|_| {
-			let (offset, name_id) = uleb128(&block_info[block_ptr..]).unwrap();
-			block_ptr += offset;
-
-			let (offset, param_count) = uleb128(&block_info[block_ptr..]).unwrap();
-			block_ptr += offset;
-
-			let (offset, blocks_count) = uleb128(&block_info[block_ptr..]).unwrap();
-			block_ptr += offset;
-
-			let first_block_id = if blocks_count > 0 {
-				let (offset, first_block_id) = uleb128(&block_info[block_ptr..]).unwrap();
-				block_ptr += offset;
-				Some(first_block_id)
-			} else {
-				None
-			};
-			// Name of the block
-			// Amount of non-block fields
-			// Amount of child-blocks
-			// If it has child-blocks, starting index of said block
-			(
-				block_id_to_name(name_id),
-				param_count,
-				blocks_count,
-				first_block_id,
-			)
+	(0..sections.blocks_count).map(move |_| {
+		let (start, name_id) = next_block_uleb()?;
+		let (_, param_count) = next_block_uleb()?;
+		let (mut end, blocks_count) = next_block_uleb()?;
+		let first_block = if blocks_count > 0 {
+			let (first_block_end, first_block) = next_block_uleb()?;
+			end = first_block_end;
+			Some(first_block)
+		} else {
+			None
+		};
+		Ok(RawBlock {
+			offset: start.start,
+			len: end.end - start.start,
+			name_id,
+			param_count,
+			blocks_count,
+			first_block,
 		})
+	})
+}
+
+/// Position and extent of a block's parameters and children, validated by [`index_blocks`]
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct BlockInfo {
+	/// Location of the block record in the file
+	pub offset:       usize,
+	/// Index into the names offset by one, as 0 refers to the root
+	pub name_id:      usize,
+	pub first_param:  usize,
+	pub param_count:  usize,
+	pub first_block:  usize,
+	pub blocks_count: usize,
+}
+
+impl BlockInfo {
+	pub(crate) fn params(&self) -> Range<usize> {
+		self.first_param..self.first_param + self.param_count
+	}
+
+	pub(crate) fn blocks(&self) -> Range<usize> {
+		self.first_block..self.first_block + self.blocks_count
+	}
+}
+
+/// Decodes all block records and checks that they form a tree of in-bounds parameters and names.
+/// The returned blocks can be indexed without further bounds checks
+pub(crate) fn index_blocks(
+	sections: &BlkSections,
+	names_len: usize,
+) -> Result<Vec<BlockInfo>, ParseError> {
+	let name_oob = |index| ParseError::NameIndexOutOfBounds {
+		index,
+		len: names_len,
 	};
 
-	// Create a flat hierarchy of all blocks including their non-block fields
-	// This ensures all values are actually assigned
-	// After this, the hierarchy will be assigned depth depending on the block-map
-	let mut flat_map: Vec<Option<FlatBlock>> = Vec::with_capacity(blocks_count);
-	let mut ptr = 0;
-	for (name, field_count, blocks, offset) in blocks {
-		let mut field = FlatBlock {
-			name,
-			fields: Vec::with_capacity(field_count),
-			blocks,
-			offset: offset.unwrap_or(0),
-		};
-		for i in (ptr)..(ptr + field_count) {
-			field.fields.push(
-				results[i]
-					.1
-					.take()
-					.expect("Infallible, already taken value"),
-			);
+	let params_count = sections.params_count;
+	for (i, chunk) in sections.params_info.chunks_exact(8).enumerate() {
+		let name_id = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], 0]) as usize;
+		if name_id >= names_len {
+			return Err(name_oob(name_id).at(sections.param_location(i).with_param(
+				ParamLocation {
+					index:   i,
+					name:    None,
+					type_id: chunk[3],
+				},
+			)));
 		}
-		ptr += field_count;
-		flat_map.push(Some(field));
 	}
-	#[cfg(debug_assertions)]
-	assert!(
-		results.into_iter().all(|e| e.1.is_none()),
-		"unused values in results"
-	);
-
-	let out = BlkField::from_flat_blocks(&mut flat_map)
-		.map_err(|e| ParseError::BlkBlockBuilderError(e))?;
-	Ok(out)
+
+	// Each block occupies at least 3 bytes, which bounds the allocation for bogus block counts
+	let mut blocks = Vec::with_capacity(sections.blocks_count.min(sections.block_info.len() / 3));
+	let mut first_param = 0_usize;
+	for block in raw_blocks(sections) {
+		let block = block?;
+		let location = ErrorLocation::new(block.offset, BlkSection::BlockInfo);
+		if block.name_id > names_len {
+			return Err(name_oob(block.name_id - 1).at(location));
+		}
+		let params = first_param..first_param.saturating_add(block.param_count);
+		if params.end > params_count {
+			return Err(ParseError::BlockParamsExceeded {
+				claimed:   params,
+				available: params_count,
+			}
+			.at(location));
+		}
+		blocks.push(BlockInfo {
+			offset:       block.offset,
+			name_id:      block.name_id,
+			first_param:  params.start,
+			param_count:  block.param_count,
+			first_block:  block.first_block.unwrap_or(0),
+			blocks_count: block.blocks_count,
+		});
+		first_param = params.end;
+	}
+	if blocks.is_empty() {
+		return Err(
+			ParseError::BlkBlockBuilderError(BlkBlockBuilderError::InitialElementMissing).at(
+				ErrorLocation::new(
+					sections.offset_of(sections.block_info),
+					BlkSection::BlockInfo,
+				),
+			),
+		);
+	}
+
+	// Every block but the root has to be the child of exactly one block that precedes it,
+	// which rules out cycles and shared children
+	let mut claimed = vec![false; blocks.len()];
+	for (id, block) in blocks.iter().enumerate() {
+		if block.blocks_count == 0 {
+			continue;
+		}
+		let location = ErrorLocation::new(block.offset, BlkSection::BlockInfo);
+		let range = block.first_block..block.first_block.saturating_add(block.blocks_count);
+		if range.start <= id || range.end > blocks.len() {
+			return Err(ParseError::BlkBlockBuilderError(
+				BlkBlockBuilderError::BlockRangeOutOfBounds {
+					range,
+					len: blocks.len(),
+				},
+			)
+			.at(location));
+		}
+		for child in &mut claimed[range] {
+			if *child {
+				return Err(ParseError::BlkBlockBuilderError(
+					BlkBlockBuilderError::TakenElementMissing,
+				)
+				.at(location));
+			}
+			*child = true;
+		}
+	}
+
+	if first_param != params_count {
+		return Err(ParseError::UnclaimedParams.at(sections.param_location(first_param)));
+	}
+	if let Some(unclaimed) = claimed.iter().skip(1).position(|&child| !child) {
+		return Err(
+			ParseError::BlkBlockBuilderError(BlkBlockBuilderError::UnclaimedElements).at(
+				ErrorLocation::new(blocks[unclaimed + 1].offset, BlkSection::BlockInfo),
+			),
+		);
+	}
+
+	Ok(blocks)
 }
@@ -0,0 +1,507 @@
+use std::{borrow::Cow, ops::Range};
+
+use crate::blk::{
+	binary_deserialize::parser::{index_blocks, split_param_info, BlkSections, BlockInfo},
+	blk_block_hierarchy::{BlkBlockBuilderError, MAX_BLOCK_DEPTH},
+	blk_structure::BlkField,
+	blk_type::{blk_type_id::*, BlkType, UnknownValue},
+	error::{BlkSection, ErrorLocation, ParamLocation, ParseError},
+	nm_file::NameMap,
+	util::{blk_str, bytes_to_float, bytes_to_int, bytes_to_long, bytes_to_offset, bytes_to_uint},
+};
+
+/// Borrowed view over a binary BLK.
+/// Construction indexes the names and blocks once, everything else is resolved when it is requested
+#[derive(Debug, Clone)]
+pub struct BlkView<'a> {
+	/// FAT names are located by their range in the names section, SLIM names live in the name map
+	names:              Names<'a>,
+	params_data:        &'a [u8],
+	params_info:        &'a [u8],
+	/// Offset of the params info section, such that errors can point at the bad record
+	params_info_offset: usize,
+	blocks:             Vec<BlockInfo>,
+	/// Region that non-name-map strings are read from
+	strings:            &'a [u8],
+}
+
+#[derive(Debug, Clone)]
+enum Names<'a> {
+	Fat {
+		data:   &'a [u8],
+		ranges: Vec<Range<usize>>,
+	},
+	Slim(&'a NameMap),
+}
+
+impl<'a> BlkView<'a> {
+	/// Indexes the file, which must not include the leading [`crate::blk::file::FileType`] byte.
+	/// All indices are validated here, so that accessing the view afterwards only fails on malformed values
+	pub fn new(
+		file: &'a [u8],
+		is_slim: bool,
+		shared_name_map: Option<&'a NameMap>,
+	) -> Result<Self, ParseError> {
+		let sections = BlkSections::split(file, is_slim)?;
+
+		let (names, strings) = match sections.names_data {
+			Some(data) => {
+				let mut ranges = Vec::with_capacity(sections.names_count.min(data.len()));
+				let mut start = 0;
+				for (i, &byte) in data.iter().enumerate() {
+					if byte == 0 {
+						ranges.push(start..i);
+						start = i + 1;
+					}
+				}
+				(Names::Fat { data, ranges }, sections.params_data)
+			},
+			None => {
+				let nm = shared_name_map.ok_or(ParseError::SlimBlkWithoutNm)?;
+				(Names::Slim(nm), nm.binary.as_slice())
+			},
+		};
+		let blocks = index_blocks(&sections, names.len())?;
+
+		Ok(Self {
+			names,
+			params_data: sections.params_data,
+			params_info: sections.params_info,
+			params_info_offset: sections.offset_of(sections.params_info),
+			blocks,
+			strings,
+		})
+	}
+
+	pub fn root(&self) -> BlockView<'_, 'a> {
+		BlockView {
+			view: self,
+			id:   0,
+		}
+	}
+
+	pub fn blocks_count(&self) -> usize {
+		self.blocks.len()
+	}
+
+	pub fn params_count(&self) -> usize {
+		self.params_info.len() / 8
+	}
+
+	/// Builds the same tree as [`crate::blk::binary_deserialize::parser::parse_blk`],
+	/// keeping params of unknown types as if [`crate::blk::binary_deserialize::parser::ParseOptions::keep_unknown_types`] was set
+	pub fn to_blk_field(&self) -> Result<BlkField, ParseError> {
+		self.root().to_blk_field()
+	}
+
+	fn name(&self, id: usize) -> Cow<'a, str> {
+		match &self.names {
+			Names::Fat { data, ranges } => ranges
+				.get(id)
+				.and_then(|range| data.get(range.clone()))
+				.map(String::from_utf8_lossy)
+				.unwrap_or_default(),
+			Names::Slim(nm) => nm
+				.parsed
+				.get(id)
+				.map(|name| Cow::Borrowed(name.as_str()))
+				.unwrap_or_default(),
+		}
+	}
+
+	fn name_bytes(&self, id: usize) -> &'a [u8] {
+		match &self.names {
+			Names::Fat { data, ranges } => ranges
+				.get(id)
+				.and_then(|range| data.get(range.clone()))
+				.unwrap_or_default(),
+			Names::Slim(nm) => nm
+				.parsed
+				.get(id)
+				.map(|name| name.as_bytes())
+				.unwrap_or_default(),
+		}
+	}
+
+	fn param_info(&self, index: usize) -> Option<(usize, u8, &'a [u8])> {
+		let chunk = self.params_info.get(index * 8..index * 8 + 8)?;
+		Some(split_param_info(chunk.try_into().ok()?))
+	}
+}
+
+impl Names<'_> {
+	fn len(&self) -> usize {
+		match self {
+			Names::Fat { ranges, .. } => ranges.len(),
+			Names::Slim(nm) => nm.parsed.len(),
+		}
+	}
+}
+
+/// A single block of a [`BlkView`], including the root
+#[derive(Copy, Clone, Debug)]
+pub struct BlockView<'v, 'a> {
+	view: &'v BlkView<'a>,
+	id:   usize,
+}
+
+impl<'v, 'a> BlockView<'v, 'a> {
+	pub fn name(&self) -> Cow<'a, str> {
+		match self.info().name_id {
+			0 => Cow::Borrowed("root"),
+			id => self.view.name(id - 1),
+		}
+	}
+
+	pub fn params(&self) -> impl ExactSizeIterator<Item = ParamView<'v, 'a>> + 'v {
+		let view = self.view;
+		let info = self.info();
+		(info.first_param..info.first_param + info.param_count)
+			.map(move |index| ParamView { view, index })
+	}
+
+	pub fn blocks(&self) -> impl ExactSizeIterator<Item = BlockView<'v, 'a>> + 'v {
+		let view = self.view;
+		let info = self.info();
+		(info.first_block..info.first_block + info.blocks_count)
+			.map(move |id| BlockView { view, id })
+	}
+
+	/// First parameter with the given name
+	pub fn param(&self, name: &str) -> Option<ParamView<'v, 'a>> {
+		self.params()
+			.find(|param| self.view.name_bytes(param.name_id()) == name.as_bytes())
+	}
+
+	/// First child block with the given name
+	pub fn block(&self, name: &str) -> Option<BlockView<'v, 'a>> {
+		self.blocks().find(|block| {
+			block.info().name_id != 0
+				&& self.view.name_bytes(block.info().name_id - 1) == name.as_bytes()
+		})
+	}
+
+	pub fn to_blk_field(self) -> Result<BlkField, ParseError> {
+		self.to_blk_field_at_depth(0)
+	}
+
+	fn to_blk_field_at_depth(self, depth: usize) -> Result<BlkField, ParseError> {
+		if depth > MAX_BLOCK_DEPTH {
+			return Err(ParseError::BlkBlockBuilderError(
+				BlkBlockBuilderError::NestingTooDeep,
+			));
+		}
+		let mut fields = Vec::with_capacity(self.info().param_count + self.info().blocks_count);
+		for param in self.params() {
+			fields.push(BlkField::Value(
+				param.name().into_owned().into(),
+				param.value()?.into(),
+			));
+		}
+		for block in self.blocks() {
+			fields.push(block.to_blk_field_at_depth(depth + 1)?);
+		}
+		Ok(BlkField::Struct(self.name().into_owned().into(), fields))
+	}
+
+	fn info(&self) -> BlockInfo {
+		self.view.blocks[self.id]
+	}
+}
+
+/// A single parameter of a [`BlockView`], which is decoded when its value is requested
+#[derive(Copy, Clone, Debug)]
+pub struct ParamView<'v, 'a> {
+	view:  &'v BlkView<'a>,
+	index: usize,
+}
+
+impl<'a> ParamView<'_, 'a> {
+	pub fn name(&self) -> Cow<'a, str> {
+		self.view.name(self.name_id())
+	}
+
+	pub fn type_id(&self) -> u8 {
+		self.view
+			.param_info(self.index)
+			.map_or(0, |(_, type_id, _)| type_id)
+	}
+
+	pub fn value(&self) -> Result<BlkValueRef<'a>, ParseError> {
+		self.decode().ok_or_else(|| {
+			ParseError::BadBlkValue.at(ErrorLocation::new(
+				self.view.params_info_offset + self.index * 8,
+				BlkSection::ParamsInfo,
+			)
+			.with_param(ParamLocation {
+				index:   self.index,
+				name:    Some(blk_str(&self.name())),
+				type_id: self.type_id(),
+			}))
+		})
+	}
+
+	fn decode(&self) -> Option<BlkValueRef<'a>> {
+		let (_, type_id, field) = self.view.param_info(self.index)?;
+		if type_id == STRING {
+			let offset = bytes_to_uint(field)?;
+			// The highest bit indicates that the string is a name, the remaining bits are the offset or index
+			let index = (i32::MAX as u32 & offset) as usize;
+			let value = if (offset >> 31) == 1 {
+				if index >= self.view.names.len() {
+					return None;
+				}
+				self.view.name(index)
+			} else {
+				let region = self.view.strings.get(index..)?;
+				let len = region.iter().position(|&b| b == 0).unwrap_or(region.len());
+				String::from_utf8_lossy(&region[..len])
+			};
+			Some(BlkValueRef::Str(value))
+		} else if is_known(type_id) {
+			BlkValueRef::from_raw_param_info(type_id, field, self.view.params_data)
+		} else {
+			BlkValueRef::unknown(
+				type_id,
+				field,
+				self.view.params_info,
+				self.view.params_data,
+				self.view.strings,
+			)
+		}
+	}
+
+	fn name_id(&self) -> usize {
+		self.view
+			.param_info(self.index)
+			.map_or(0, |(name_id, ..)| name_id)
+	}
+}
+
+/// Borrowed counterpart of [`BlkType`], which neither allocates nor boxes
+#[derive(Debug, PartialOrd, PartialEq, Clone)]
+pub enum BlkValueRef<'a> {
+	Str(Cow<'a, str>),
+	Int(i32),
+	Int2([i32; 2]),
+	Int3([i32; 3]),
+	Long(i64),
+	Float(f32),
+	Float2([f32; 2]),
+	Float3([f32; 3]),
+	Float4([f32; 4]),
+	/// 3x4 Transformation matrix
+	Float12([f32; 12]),
+	Bool(bool),
+	Color {
+		r: u8,
+		g: u8,
+		b: u8,
+		a: u8,
+	},
+	/// Type id this crate does not know, see [`UnknownValue`]
+	Unknown {
+		type_id: u8,
+		raw:     [u8; 4],
+		data:    &'a [u8],
+	},
+}
+
+impl<'a> BlkValueRef<'a> {
+	/// Decodes all types but strings, which depend on where names are stored.
+	/// Arguments match [`BlkType::from_raw_param_info`]
+	pub(crate) fn from_raw_param_info(
+		type_id: u8,
+		field: &[u8],
+		data_region: &[u8],
+	) -> Option<Self> {
+		// Yields `len` bytes from the data region, at the offset stored in the field
+		let data_at = |len: usize| {
+			let offset = bytes_to_offset(field)?;
+			data_region.get(offset..offset.checked_add(len)?)
+		};
+		Some(match type_id {
+			INT => Self::Int(bytes_to_int(field)?),
+			FLOAT => Self::Float(bytes_to_float(field)?),
+			FLOAT2 => Self::Float2(floats(data_at(8)?)?),
+			FLOAT3 => Self::Float3(floats(data_at(12)?)?),
+			FLOAT4 => Self::Float4(floats(data_at(16)?)?),
+			FLOAT12 => Self::Float12(floats(data_at(48)?)?),
+			INT2 => Self::Int2(ints(data_at(8)?)?),
+			INT3 => Self::Int3(ints(data_at(12)?)?),
+			BOOL => Self::Bool(*field.first()? != 0),
+			COLOR => {
+				// Game stores them in BGRA order
+				let &[r, g, b, a] = field else {
+					return None;
+				};
+				Self::Color { r, g, b, a }
+			},
+			LONG => Self::Long(bytes_to_long(data_at(8)?)?),
+			_ => return None,
+		})
+	}
+
+	/// Keeps the raw field of a param with an unknown type, together with the data region bytes it may point at.
+	/// Values in the data region are not length prefixed, so they are assumed to end where the next value
+	/// referenced by `params_info` begins. `strings` is the region strings are read from
+	pub(crate) fn unknown(
+		type_id: u8,
+		field: &[u8],
+		params_info: &[u8],
+		params_data: &'a [u8],
+		strings: &[u8],
+	) -> Option<Self> {
+		let raw: [u8; 4] = field.try_into().ok()?;
+		let start = u32::from_le_bytes(raw) as usize;
+		let data = if start < params_data.len() {
+			let strings_in_data = std::ptr::eq(strings, params_data);
+			let end = params_info
+				.chunks_exact(8)
+				.filter_map(|chunk| {
+					let (_, type_id, field) = split_param_info(chunk.try_into().ok()?);
+					let offset = bytes_to_uint(field)?;
+					match type_id {
+						INT2 | INT3 | LONG | FLOAT2 | FLOAT3 | FLOAT4 | FLOAT12 => Some(offset),
+						// The highest bit marks strings from the name map
+						STRING if strings_in_data && offset >> 31 == 0 => Some(offset),
+						_ => None,
+					}
+				})
+				.map(|offset| offset as usize)
+				.filter(|&offset| offset > start)
+				.min()
+				.map_or(params_data.len(), |end| end.min(params_data.len()));
+			&params_data[start..end]
+		} else {
+			&[]
+		};
+		Some(Self::Unknown { type_id, raw, data })
+	}
+}
+
+fn floats<const N: usize>(data: &[u8]) -> Option<[f32; N]> {
+	let mut out = [0.0; N];
+	for (value, chunk) in out.iter_mut().zip(data.chunks_exact(4)) {
+		*value = bytes_to_float(chunk)?;
+	}
+	Some(out)
+}
+
+fn ints<const N: usize>(data: &[u8]) -> Option<[i32; N]> {
+	let mut out = [0; N];
+	for (value, chunk) in out.iter_mut().zip(data.chunks_exact(4)) {
+		*value = bytes_to_int(chunk)?;
+	}
+	Some(out)
+}
+
+impl From<BlkValueRef<'_>> for BlkType {
+	fn from(value: BlkValueRef<'_>) -> Self {
+		match value {
+			BlkValueRef::Str(v) => BlkType::Str(v.into_owned().into()),
+			BlkValueRef::Int(v) => BlkType::Int(v),
+			BlkValueRef::Int2(v) => BlkType::Int2(v),
+			BlkValueRef::Int3(v) => BlkType::Int3(v),
+			BlkValueRef::Long(v) => BlkType::Long(v),
+			BlkValueRef::Float(v) => BlkType::Float(v),
+			BlkValueRef::Float2(v) => BlkType::Float2(v),
+			BlkValueRef::Float3(v) => BlkType::Float3(v),
+			BlkValueRef::Float4(v) => BlkType::Float4(Box::new(v)),
+			BlkValueRef::Float12(v) => BlkType::Float12(Box::new(v)),
+			BlkValueRef::Bool(v) => BlkType::Bool(v),
+			BlkValueRef::Color { r, g, b, a } => BlkType::Color { r, g, b, a },
+			BlkValueRef::Unknown { type_id, raw, data } => {
+				BlkType::Unknown(Box::new(UnknownValue {
+					type_id,
+					raw,
+					data: data.to_vec(),
+				}))
+			},
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::{fs, sync::Arc};
+
+	use crate::blk::{
+		binary_deserialize::{
+			parser::{parse_blk, parse_blk_with_options, BlkSections, ParseOptions},
+			view::{BlkValueRef, BlkView},
+		},
+		nm_file::NameMap,
+	};
+
+	#[test]
+	fn fat_matches_parse_blk() {
+		let file = fs::read("./samples/section_fat.blk").unwrap();
+		let view = BlkView::new(&file[1..], false, None).unwrap();
+		let expected = parse_blk(&file[1..], false, None).unwrap();
+		assert_eq!(view.to_blk_field().unwrap(), expected);
+	}
+
+	#[test]
+	fn slim_matches_parse_blk() {
+		let nm = NameMap::from_encoded_file(&fs::read("./samples/nm").unwrap()).unwrap();
+		let file = fs::read("./samples/section_slim.blk").unwrap();
+		let view = BlkView::new(&file[1..], true, Some(&nm)).unwrap();
+		let expected = parse_blk(&file[1..], true, Some(Arc::new(nm.clone()))).unwrap();
+		assert_eq!(view.to_blk_field().unwrap(), expected);
+	}
+
+	#[test]
+	fn lookup() {
+		let file = fs::read("./samples/section_fat.blk").unwrap();
+		let view = BlkView::new(&file[1..], false, None).unwrap();
+		let alpha = view.root().block("alpha").unwrap();
+		assert_eq!(
+			alpha.param("str").unwrap().value().unwrap(),
+			BlkValueRef::Str("hello".into())
+		);
+		assert_eq!(
+			alpha
+				.block("gamma")
+				.unwrap()
+				.param("vec2i")
+				.unwrap()
+				.value()
+				.unwrap(),
+			BlkValueRef::Int2([3, 4])
+		);
+		assert!(view.root().param("missing").is_none());
+	}
+
+	#[test]
+	fn unknown_type() {
+		let file = fs::read("./samples/section_fat.blk").unwrap();
+		let mut file = file[1..].to_vec();
+		let params_info = BlkSections::split(&file, false)
+			.unwrap()
+			.param_location(0)
+			.offset;
+		file[params_info + 3] = 0x1F;
+
+		let view = BlkView::new(&file, false, None).unwrap();
+		let BlkValueRef::Unknown { type_id, data, .. } =
+			view.root().param("vec4f").unwrap().value().unwrap()
+		else {
+			panic!("vec4f should be unknown")
+		};
+		assert_eq!((type_id, data.len()), (0x1F, 16));
+		assert_eq!(
+			view.to_blk_field().unwrap(),
+			parse_blk_with_options(
+				&file,
+				false,
+				None,
+				ParseOptions {
+					keep_unknown_types: true,
+					..Default::default()
+				},
+			)
+			.unwrap()
+		);
+	}
+}
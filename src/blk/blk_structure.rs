@@ -1,12 +1,13 @@
 use std::{fmt::Debug, iter::Peekable, mem};
-use color_eyre::eyre::bail;
-use color_eyre::Report;
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 
-use crate::blk::{
-	blk_type::{BlkString, BlkType},
-	util::blk_str,
+use crate::{
+	blk::{
+		blk_type::{BlkString, BlkType},
+		util::blk_str,
+	},
+	error::WtBlkError,
 };
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -15,8 +16,8 @@ pub enum BlkField {
 	Value(BlkString, BlkType),
 	// Name and fields of substructs
 	Struct(BlkString, Vec<BlkField>),
-	// Array of merged fields that were duplicated in a Struct
-	Merged(BlkString, Vec<BlkField>),
+	// Array of merged fields that were duplicated in a Struct, and the positions they originally held in said Struct
+	Merged(BlkString, Vec<BlkField>, Vec<usize>),
 }
 
 impl BlkField {
@@ -72,7 +73,7 @@ impl BlkField {
 
 	pub fn get_name(&self) -> BlkString {
 		match self {
-			BlkField::Value(name, _) | BlkField::Struct(name, _) | BlkField::Merged(name, _) => {
+			BlkField::Value(name, _) | BlkField::Struct(name, _) | BlkField::Merged(name, ..) => {
 				name.clone()
 			},
 		}
@@ -80,7 +81,7 @@ impl BlkField {
 
 	pub fn set_name(&mut self, new: BlkString) {
 		match self {
-			BlkField::Value(name, _) | BlkField::Struct(name, _) | BlkField::Merged(name, _) => {
+			BlkField::Value(name, _) | BlkField::Struct(name, _) | BlkField::Merged(name, ..) => {
 				*name = new;
 			},
 		}
@@ -95,7 +96,7 @@ impl BlkField {
 		}
 	}
 
-	pub fn pointer(&self, ptr: &str) -> Result<BlkField, Report> {
+	pub fn pointer(&self, ptr: &str) -> Result<BlkField, WtBlkError> {
 		let commands = ptr.split("/");
 		self.pointer_internal(ptr, &mut commands.into_iter().peekable())
 	}
@@ -104,26 +105,30 @@ impl BlkField {
 		&self,
 		ptr: &str,
 		pointers: &mut Peekable<impl Iterator<Item = &'a str>>,
-	) -> Result<BlkField, Report> {
+	) -> Result<BlkField, WtBlkError> {
+		let invalid = |reason| WtBlkError::InvalidPointer {
+			pointer: ptr.to_owned(),
+			reason,
+		};
 		let current_search = pointers.next();
 		match self {
 			BlkField::Value(_k, _v) => {
 				if let Some(_) = current_search {
-					bail!("Did not expect end but ended up in value")
+					Err(invalid("Did not expect end but ended up in value"))
 				} else {
 					Ok(self.clone())
 				}
 			},
-			BlkField::Struct(_k, v) | BlkField::Merged(_k, v) => {
+			BlkField::Struct(_k, v) | BlkField::Merged(_k, v, _) => {
 				if let Some(search) = current_search {
 					for value in v {
 						if value.get_name().as_str() == search {
 							return value.pointer_internal(ptr, pointers);
 						}
 					}
-					bail!("Substructure not in struct")
+					Err(invalid("Substructure not in struct"))
 				} else {
-					bail!("Search ended before finding target")
+					Err(invalid("Search ended before finding target"))
 				}
 			},
 		}
@@ -141,7 +146,7 @@ impl BlkField {
 				*total += key.len();
 				*total += value.size_bytes();
 			},
-			BlkField::Struct(key, fields) | BlkField::Merged(key, fields) => {
+			BlkField::Struct(key, fields) | BlkField::Merged(key, fields, _) => {
 				*total += key.len();
 				for field in fields {
 					field._estimate_size(total);
@@ -149,6 +154,58 @@ impl BlkField {
 			},
 		}
 	}
+
+	/// Yields `(field index, index inside of merged array)` pairs for the fields of a struct,
+	/// in the order they were in before [`BlkField::merge_fields`] was applied.
+	/// Merged arrays without valid positions are expanded where they currently are
+	pub(crate) fn unmerged_order(fields: &[BlkField]) -> Vec<(usize, usize)> {
+		let total = fields
+			.iter()
+			.map(|field| match field {
+				BlkField::Merged(_, values, _) => values.len(),
+				_ => 1,
+			})
+			.sum();
+
+		let mut slots: Vec<Option<(usize, usize)>> = vec![None; total];
+		// Fields that do not know their original position fill the gaps in order
+		let mut remaining = Vec::with_capacity(total);
+		for (i, field) in fields.iter().enumerate() {
+			match field {
+				BlkField::Merged(_, values, positions) => {
+					let placed = if positions.len() == values.len() {
+						positions
+							.iter()
+							.enumerate()
+							.take_while(|&(j, &pos)| match slots.get_mut(pos) {
+								Some(slot @ None) => {
+									*slot = Some((i, j));
+									true
+								},
+								_ => false,
+							})
+							.count()
+					} else {
+						0
+					};
+					// Out of bounds or duplicate positions, undo the partial placement
+					if placed != values.len() {
+						for &pos in &positions[..placed] {
+							slots[pos] = None;
+						}
+						remaining.extend((0..values.len()).map(|j| (i, j)));
+					}
+				},
+				_ => remaining.push((i, 0)),
+			}
+		}
+
+		let mut remaining = remaining.into_iter();
+		slots
+			.into_iter()
+			.map(|slot| slot.or_else(|| remaining.next()).expect("Infallible"))
+			.collect()
+	}
 }
 
 #[cfg(test)]
@@ -194,4 +251,20 @@ mod test {
 
 		assert_eq!(after, before);
 	}
+
+	#[test]
+	fn duplicate_merged_positions() {
+		let fields = [
+			BlkField::Value(blk_str("a"), BlkType::Int(0)),
+			BlkField::Merged(
+				blk_str("b"),
+				vec![
+					BlkField::Value(blk_str("b"), BlkType::Int(1)),
+					BlkField::Value(blk_str("b"), BlkType::Int(2)),
+				],
+				vec![2, 2],
+			),
+		];
+		assert_eq!(BlkField::unmerged_order(&fields), [(0, 0), (1, 0), (1, 1)]);
+	}
 }
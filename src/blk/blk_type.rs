@@ -5,16 +5,15 @@ use std::{
 	sync::Arc,
 };
 
-use color_eyre::Report;
 use serde::{Deserialize, Serialize, Serializer};
-use serde_json::{
-	ser::{Formatter, PrettyFormatter},
-	Serializer as JsonSerializer,
-};
+use serde_json::{ser::Formatter, Serializer as JsonSerializer};
 
 use crate::blk::{
+	binary_deserialize::view::BlkValueRef,
 	blk_type::blk_type_id::*,
-	util::{bytes_to_float, bytes_to_int, bytes_to_long, bytes_to_offset, bytes_to_uint},
+	interner::StringInterner,
+	plaintext_serialize::json_format::{ColorFormat, JsonFormat, MatrixFormat},
+	util::bytes_to_uint,
 };
 
 pub type BlkString = Arc<String>;
@@ -32,6 +31,11 @@ pub mod blk_type_id {
 	pub const FLOAT12: u8 = 0x0B;
 	pub const BOOL: u8 = 0x09;
 	pub const COLOR: u8 = 0x0A;
+
+	/// Whether the type id is one of the above
+	pub const fn is_known(type_id: u8) -> bool {
+		matches!(type_id, STRING..=LONG)
+	}
 }
 
 /// Asserts the size of BlkType is constant
@@ -67,6 +71,21 @@ pub enum BlkType {
 		b: u8,
 		a: u8,
 	},
+	/// Type id this crate does not know, kept as its raw bytes.
+	/// Only produced when [`crate::blk::binary_deserialize::parser::ParseOptions::keep_unknown_types`] is set
+	Unknown(Box<UnknownValue>),
+}
+
+/// Raw bytes of a param whose type id this crate does not know
+#[derive(Debug, PartialOrd, PartialEq, Clone, Serialize, Deserialize)]
+pub struct UnknownValue {
+	pub type_id: u8,
+	/// The 4 byte field, which holds either the value itself or its offset into the data region
+	pub raw:     [u8; 4],
+	/// Data region bytes from the offset in `raw` up to the next value stored there, or the end of the region.
+	/// Empty when `raw` is no offset into the data region.
+	/// Whether the type is stored inline is not known, so both are kept
+	pub data:    Vec<u8>,
 }
 
 impl BlkType {
@@ -78,6 +97,17 @@ impl BlkType {
 		field: &[u8],
 		data_region: &[u8],
 		name_map: &[BlkString],
+	) -> Option<Self> {
+		Self::from_raw_param_info_interned(type_id, field, data_region, name_map, None)
+	}
+
+	/// Same as [`BlkType::from_raw_param_info`], sharing strings from the data region through the interner
+	pub fn from_raw_param_info_interned(
+		type_id: u8,
+		field: &[u8],
+		data_region: &[u8],
+		name_map: &[BlkString],
+		interner: Option<&StringInterner>,
 	) -> Option<Self> {
 		match type_id {
 			STRING => {
@@ -89,101 +119,23 @@ impl BlkType {
 				let in_nm = (offset >> 31) == 1; // Compare first bit to check where to look
 				let offset = i32::MAX as u32 & offset; // Set first byte to 0
 				let res: BlkString = if in_nm {
-					name_map[offset as usize].clone()
+					name_map.get(offset as usize)?.clone()
 				} else {
-					let data_region = &data_region[(offset as usize)..];
-					let mut buff = Vec::with_capacity(32);
-					for &byte in data_region {
-						if byte == 0 {
-							break;
-						}
-						buff.push(byte)
+					let data_region = data_region.get((offset as usize)..)?;
+					let len = data_region
+						.iter()
+						.position(|&b| b == 0)
+						.unwrap_or(data_region.len());
+					let s = String::from_utf8_lossy(&data_region[..len]);
+					match interner {
+						Some(interner) => interner.intern(&s),
+						None => Arc::from(s.into_owned()),
 					}
-					Arc::from(String::from_utf8_lossy(&buff).to_string())
 				};
 
 				Some(Self::Str(res))
 			},
-			INT => Some(Self::Int(bytes_to_int(field)?)),
-			FLOAT => Some(Self::Float(bytes_to_float(field)?)),
-			FLOAT2 => {
-				let offset = bytes_to_offset(field)?;
-				let data_region = &data_region[offset..(offset + 8)];
-				Some(Self::Float2([
-					bytes_to_float(&data_region[0..4])?,
-					bytes_to_float(&data_region[4..8])?,
-				]))
-			},
-			FLOAT3 => {
-				let offset = bytes_to_offset(field)?;
-				let data_region = &data_region[offset..(offset + 12)];
-				Some(Self::Float3([
-					bytes_to_float(&data_region[0..4])?,
-					bytes_to_float(&data_region[4..8])?,
-					bytes_to_float(&data_region[8..12])?,
-				]))
-			},
-			FLOAT4 => {
-				let offset = bytes_to_offset(field)?;
-				let data_region = &data_region[offset..(offset + 16)];
-				Some(Self::Float4(Box::new([
-					bytes_to_float(&data_region[0..4])?,
-					bytes_to_float(&data_region[4..8])?,
-					bytes_to_float(&data_region[8..12])?,
-					bytes_to_float(&data_region[12..16])?,
-				])))
-			},
-			INT2 => {
-				let offset = bytes_to_offset(field)?;
-				let data_region = &data_region[offset..(offset + 8)];
-				Some(Self::Int2([
-					bytes_to_int(&data_region[0..4])?,
-					bytes_to_int(&data_region[4..8])?,
-				]))
-			},
-			INT3 => {
-				let offset = bytes_to_offset(field)?;
-				let data_region = &data_region[offset..(offset + 12)];
-				Some(Self::Int3([
-					bytes_to_int(&data_region[0..4])?,
-					bytes_to_int(&data_region[4..8])?,
-					bytes_to_int(&data_region[8..12])?,
-				]))
-			},
-			BOOL => Some(Self::Bool(field[0] != 0)),
-			COLOR => {
-				// Game stores them in BGRA order
-				Some(Self::Color {
-					r: field[0],
-					g: field[1],
-					b: field[2],
-					a: field[3],
-				})
-			},
-			FLOAT12 => {
-				let offset = bytes_to_offset(field)?;
-				let data_region = &data_region[offset..(offset + 48)];
-				Some(Self::Float12(Box::new([
-					bytes_to_float(&data_region[0..4])?,
-					bytes_to_float(&data_region[4..8])?,
-					bytes_to_float(&data_region[8..12])?,
-					bytes_to_float(&data_region[12..16])?,
-					bytes_to_float(&data_region[16..20])?,
-					bytes_to_float(&data_region[20..24])?,
-					bytes_to_float(&data_region[24..28])?,
-					bytes_to_float(&data_region[28..32])?,
-					bytes_to_float(&data_region[32..36])?,
-					bytes_to_float(&data_region[36..40])?,
-					bytes_to_float(&data_region[40..44])?,
-					bytes_to_float(&data_region[44..48])?,
-				])))
-			},
-			LONG => {
-				let offset = bytes_to_offset(field)?;
-				let data_region = &data_region[offset..(offset + 8)];
-				Some(Self::Long(bytes_to_long(data_region)?))
-			},
-			_ => None,
+			_ => BlkValueRef::from_raw_param_info(type_id, field, data_region).map(Self::from),
 		}
 	}
 
@@ -202,6 +154,7 @@ impl BlkType {
 			BlkType::Float12(_) => FLOAT12,
 			BlkType::Bool(_) => BOOL,
 			BlkType::Color { .. } => COLOR,
+			BlkType::Unknown(v) => v.type_id,
 		}
 	}
 
@@ -222,6 +175,7 @@ impl BlkType {
 			BlkType::Float12(_) => false,
 			BlkType::Bool(_) => true,
 			BlkType::Color { .. } => true,
+			BlkType::Unknown { .. } => true,
 		}
 	}
 
@@ -242,6 +196,7 @@ impl BlkType {
 			BlkType::Float12(_) => 48,
 			BlkType::Bool(_) => 4,
 			BlkType::Color { .. } => 4,
+			BlkType::Unknown(v) => 4 + v.data.len(),
 		}
 	}
 
@@ -261,6 +216,7 @@ impl BlkType {
 			BlkType::Float12(_) => "m",
 			BlkType::Bool(_) => "b",
 			BlkType::Color { .. } => "c",
+			BlkType::Unknown { .. } => "unknown",
 		}
 	}
 
@@ -274,15 +230,10 @@ impl BlkType {
 	pub fn serialize_streaming(
 		&self,
 		w: &mut impl Write,
-		ser: &mut PrettyFormatter,
-	) -> Result<(), Report> {
-		#[inline(always)]
-		/// Writes float in format that std debug uses
-		fn std_num<'b, W: Write>(_: &mut PrettyFormatter<'b>, w: &mut W, v: f32) -> io::Result<()> {
-			let mut buf = ryu::Buffer::new();
-			w.write_all(buf.format(v).as_bytes())?;
-			Ok(())
-		}
+		ser: &mut impl Formatter,
+		format: &JsonFormat,
+	) -> io::Result<()> {
+		let float = |_: &mut _, w: &mut _, v| format.float.write(w, v);
 		match self {
 			BlkType::Str(s) => {
 				// Escapes strings according to json spec
@@ -293,48 +244,57 @@ impl BlkType {
 				ser.write_i32(w, *s)?;
 			},
 			BlkType::Int2(s) => {
-				write_generic_array(PrettyFormatter::write_i32, s.iter(), w, ser)?;
+				write_generic_array(Formatter::write_i32, s.iter(), w, ser)?;
 			},
 			BlkType::Int3(s) => {
-				write_generic_array(PrettyFormatter::write_i32, s.iter(), w, ser)?;
+				write_generic_array(Formatter::write_i32, s.iter(), w, ser)?;
 			},
 			BlkType::Long(s) => {
 				ser.write_i64(w, *s)?;
 			},
 			BlkType::Float(s) => {
-				std_num(ser, w, *s)?;
+				format.float.write(w, *s)?;
 			},
-			BlkType::Float2(s) => write_generic_array(std_num, s.iter(), w, ser)?,
-			BlkType::Float3(s) => write_generic_array(std_num, s.iter(), w, ser)?,
-			BlkType::Float4(s) => write_generic_array(std_num, s.iter(), w, ser)?,
-			BlkType::Float12(s) => {
-				ser.begin_array(w)?;
-				let mut begin = true;
-				for chunk in s.chunks_exact(3) {
-					ser.begin_array_value(w, begin)?;
-					write_generic_array(std_num, chunk.iter(), w, ser)?;
-					ser.end_array_value(w)?;
-					begin = false;
-				}
-				ser.end_array(w)?;
+			BlkType::Float2(s) => write_generic_array(float, s.iter(), w, ser)?,
+			BlkType::Float3(s) => write_generic_array(float, s.iter(), w, ser)?,
+			BlkType::Float4(s) => write_generic_array(float, s.iter(), w, ser)?,
+			BlkType::Float12(s) => match format.matrix {
+				MatrixFormat::Nested => {
+					ser.begin_array(w)?;
+					let mut begin = true;
+					for chunk in s.chunks_exact(3) {
+						ser.begin_array_value(w, begin)?;
+						write_generic_array(float, chunk.iter(), w, ser)?;
+						ser.end_array_value(w)?;
+						begin = false;
+					}
+					ser.end_array(w)?;
+				},
+				MatrixFormat::Flat => write_generic_array(float, s.iter(), w, ser)?,
 			},
 			BlkType::Bool(s) => {
 				ser.write_bool(w, *s)?;
 			},
-			BlkType::Color { r, g, b, a } => {
-				write_generic_array(PrettyFormatter::write_u8, [*r, *g, *b, *a].iter(), w, ser)?
+			BlkType::Color { r, g, b, a } => match format.color {
+				ColorFormat::Array => {
+					write_generic_array(Formatter::write_u8, [*r, *g, *b, *a].iter(), w, ser)?
+				},
+				ColorFormat::Hex => write!(w, "\"#{r:02X}{g:02X}{b:02X}{a:02X}\"")?,
+			},
+			BlkType::Unknown(v) => {
+				write!(w, "\"{}\"", UnknownHex(v))?;
 			},
 		}
 		Ok(())
 	}
 }
 
-fn write_generic_array<'a, 'b, T: 'a + Copy, W: Write>(
-	writer: impl FnOnce(&mut PrettyFormatter<'b>, &mut W, T) -> io::Result<()> + Copy,
+fn write_generic_array<'a, T: 'a + Copy, W: Write, F: Formatter>(
+	writer: impl FnOnce(&mut F, &mut W, T) -> io::Result<()> + Copy,
 	mut input: impl Iterator<Item = &'a T>,
 	w: &mut W,
-	ser: &mut PrettyFormatter<'b>,
-) -> Result<(), Report> {
+	ser: &mut F,
+) -> io::Result<()> {
 	ser.begin_array(w)?;
 
 	let mut begin = true;
@@ -351,45 +311,68 @@ fn write_generic_array<'a, 'b, T: 'a + Copy, W: Write>(
 
 impl Display for BlkType {
 	fn fmt(&self, f: &mut StdFormatter<'_>) -> std::fmt::Result {
-		let value = match self {
+		write!(f, "{} = ", self.blk_type_name())?;
+		match self {
 			BlkType::Str(v) => {
-				format!("\"{}\"", v)
+				write!(f, "\"{}\"", v)
 			},
-			BlkType::Int(v) => v.to_string(),
+			BlkType::Int(v) => write!(f, "{v}"),
 			BlkType::Int2(v) => {
-				format!("{}, {}", v[0], v[1])
+				write!(f, "{}, {}", v[0], v[1])
 			},
 			BlkType::Int3(v) => {
-				format!("{}, {}, {}", v[0], v[1], v[2])
+				write!(f, "{}, {}, {}", v[0], v[1], v[2])
 			},
-			BlkType::Long(v) => v.to_string(),
-			BlkType::Float(v) => v.to_string(),
+			BlkType::Long(v) => write!(f, "{v}"),
+			BlkType::Float(v) => write!(f, "{v}"),
 			BlkType::Float2(v) => {
-				format!("{}, {}", v[0], v[1])
+				write!(f, "{}, {}", v[0], v[1])
 			},
 			BlkType::Float3(v) => {
-				format!("{}, {}, {}", v[0], v[1], v[2])
+				write!(f, "{}, {}, {}", v[0], v[1], v[2])
 			},
 			BlkType::Float4(v) => {
-				format!("{}, {}, {}, {}", v[0], v[1], v[2], v[3])
+				write!(f, "{}, {}, {}, {}", v[0], v[1], v[2], v[3])
 			},
 			BlkType::Float12(v) => {
-				format!("{:?}", *v)
+				write!(f, "{:?}", *v)
 			},
-			BlkType::Bool(v) => v.to_string(),
+			BlkType::Bool(v) => write!(f, "{v}"),
 			// BGRA
 			BlkType::Color { r, g, b, a } => {
-				format!("{b}, {g}, {r}, {a}")
+				write!(f, "{b}, {g}, {r}, {a}")
 			},
-		};
+			BlkType::Unknown(v) => write!(f, "{}", UnknownHex(v)),
+		}
+	}
+}
+
+/// Formats an unknown value as its type id, raw field and data region bytes if there are any,
+/// such as `0x1F:01020304` or `0x1F:01020304:0A0B`
+struct UnknownHex<'a>(&'a UnknownValue);
 
-		write!(f, "{} = {}", self.blk_type_name(), value)
+impl Display for UnknownHex<'_> {
+	fn fmt(&self, f: &mut StdFormatter<'_>) -> std::fmt::Result {
+		write!(f, "{:#04X}:", self.0.type_id)?;
+		for byte in self.0.raw {
+			write!(f, "{byte:02X}")?;
+		}
+		if !self.0.data.is_empty() {
+			write!(f, ":")?;
+			for byte in &self.0.data {
+				write!(f, "{byte:02X}")?;
+			}
+		}
+		Ok(())
 	}
 }
 
 #[cfg(test)]
 mod test {
-	use crate::blk::{blk_type::BlkType, util::blk_str};
+	use crate::blk::{
+		blk_type::{BlkType, UnknownValue},
+		util::blk_str,
+	};
 
 	#[test]
 	fn test_string() {
@@ -397,6 +380,24 @@ mod test {
 		assert_eq!(t.to_string(), "t = \"yeet\"")
 	}
 
+	#[test]
+	fn unknown_as_hex() {
+		let mut value = UnknownValue {
+			type_id: 0x1F,
+			raw:     [0x01, 0x02, 0xAB, 0xFF],
+			data:    vec![],
+		};
+		assert_eq!(
+			BlkType::Unknown(Box::new(value.clone())).to_string(),
+			"unknown = 0x1F:0102ABFF"
+		);
+		value.data = vec![0x0A, 0x0B];
+		assert_eq!(
+			BlkType::Unknown(Box::new(value)).to_string(),
+			"unknown = 0x1F:0102ABFF:0A0B"
+		);
+	}
+
 	#[test]
 	fn test_invalid_type() {
 		assert_eq!(BlkType::is_valid_type("invalid"), false)
@@ -1,8 +1,15 @@
-use std::{ops::Range, string::FromUtf8Error};
+use std::{
+	fmt::{Display, Formatter},
+	ops::Range,
+	string::FromUtf8Error,
+};
 
 use thiserror::Error;
 
-use crate::{binary_decoder::BinaryDecoderError, blk::blk_block_hierarchy::BlkBlockBuilderError};
+use crate::{
+	binary_decoder::BinaryDecoderError,
+	blk::{blk_block_hierarchy::BlkBlockBuilderError, blk_type::BlkString},
+};
 
 #[derive(Debug, Error, Clone, Eq, PartialEq)]
 pub enum ParseError {
@@ -12,6 +19,9 @@ pub enum ParseError {
 	#[error("Buffer ended prematurely, when current code-point expected continuation")]
 	UnexpectedEndOfBufferUleb,
 
+	#[error("ULEB var-int has more continuation bytes than fit into a usize")]
+	UlebOverflow,
+
 	#[error("Indexing into the data region was unsuccessful, most likely due to an invalid ULEB offset stemming from bad offsets")]
 	// Offset into buffer used
 	DataRegionBoundsExceeded(Range<usize>),
@@ -23,12 +33,50 @@ pub enum ParseError {
 	#[error("Blk value parsing failed")]
 	BadBlkValue,
 
+	#[error("Name index {index} out of bounds for name map of length {len}")]
+	NameIndexOutOfBounds { index: usize, len: usize },
+
+	#[error("Block claimed parameters {claimed:?}, but only {available} parameters exist")]
+	BlockParamsExceeded {
+		claimed:   Range<usize>,
+		available: usize,
+	},
+
+	#[error("Parameters remained that were not claimed by any block")]
+	UnclaimedParams,
+
 	#[error("Attempted to parse SLIM blk file without a NN")]
 	SlimBlkWithoutNm,
 
+	#[error("Name map declares {expected} names, but contains {found}")]
+	NameCountMismatch { expected: usize, found: usize },
+
 	#[error("Invalid BLK header: {header:X}")]
 	UnrecognizedBlkHeader { header: u8 },
 
+	#[error("Empty file, BLK files begin with a file type byte")]
+	EmptyFile,
+
+	#[error("BBF file does not begin with its magic bytes")]
+	BadBbfMagic,
+
+	#[error("Unsupported BBF version {version}, only version 3 is supported")]
+	UnsupportedBbfVersion { version: u16 },
+
+	#[error(
+		"BBF name reference with hash {hash:#04X} and index {index} does not resolve to a name"
+	)]
+	UnresolvedBbfName { hash: u32, index: u32 },
+
+	#[error("BBF count prefix {prefix:#04X} does not encode a width")]
+	BadBbfCountWidth { prefix: u8 },
+
+	#[error("BBF block record has type id {type_id:#04X}, but blocks always have type id 0")]
+	BadBbfBlockTypeId { type_id: u8 },
+
+	#[error("BBF string index {index} out of bounds for string table of length {len}")]
+	BbfStringIndexOutOfBounds { index: usize, len: usize },
+
 	#[error("Dictionary was invalid")]
 	InvalidDict {},
 
@@ -36,7 +84,7 @@ pub enum ParseError {
 	MissingDict {},
 
 	#[error(transparent)]
-	BinaryDecoderError(BinaryDecoderError),
+	BinaryDecoderError(#[from] BinaryDecoderError),
 
 	#[error(transparent)]
 	BlkBlockBuilderError(BlkBlockBuilderError),
@@ -46,4 +94,113 @@ pub enum ParseError {
 
 	#[error("Custom: {0}")]
 	Custom(String),
+
+	#[error("{error} ({location})")]
+	Located {
+		location: Box<ErrorLocation>,
+		error:    Box<ParseError>,
+	},
+}
+
+impl ParseError {
+	/// Attaches where the error occurred, unless it already carries a location from further down
+	pub(crate) fn at(self, location: ErrorLocation) -> Self {
+		match self {
+			ParseError::Located { .. } => self,
+			error => ParseError::Located {
+				location: Box::new(location),
+				error:    Box::new(error),
+			},
+		}
+	}
+
+	/// Where in the file the error occurred, if known
+	pub fn location(&self) -> Option<&ErrorLocation> {
+		match self {
+			ParseError::Located { location, .. } => Some(location),
+			_ => None,
+		}
+	}
+
+	/// The error itself, without its location
+	pub fn kind(&self) -> &ParseError {
+		match self {
+			ParseError::Located { error, .. } => error.kind(),
+			error => error,
+		}
+	}
+}
+
+/// Region of a binary BLK file
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum BlkSection {
+	/// Counts and sizes preceding the sections
+	Header,
+	Names,
+	ParamsData,
+	ParamsInfo,
+	BlockInfo,
+	/// String table of BBF files
+	Strings,
+}
+
+impl Display for BlkSection {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		f.write_str(match self {
+			BlkSection::Header => "header",
+			BlkSection::Names => "names",
+			BlkSection::ParamsData => "params data",
+			BlkSection::ParamsInfo => "params info",
+			BlkSection::BlockInfo => "block info",
+			BlkSection::Strings => "strings",
+		})
+	}
+}
+
+/// Position of a [`ParseError`] within the file
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ErrorLocation {
+	/// Byte offset into the buffer passed to the parser, which excludes the leading file type byte
+	pub offset:  usize,
+	pub section: BlkSection,
+	/// Parameter that was being decoded
+	pub param:   Option<ParamLocation>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ParamLocation {
+	/// Index into the params info section, or among the params of its block for BBF files
+	pub index:   usize,
+	/// Absent when the name itself could not be resolved
+	pub name:    Option<BlkString>,
+	pub type_id: u8,
+}
+
+impl ErrorLocation {
+	pub(crate) fn new(offset: usize, section: BlkSection) -> Self {
+		Self {
+			offset,
+			section,
+			param: None,
+		}
+	}
+
+	pub(crate) fn with_param(mut self, param: ParamLocation) -> Self {
+		self.param = Some(param);
+		self
+	}
+}
+
+impl Display for ErrorLocation {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		write!(f, "at byte {} in {}", self.offset, self.section)?;
+		if let Some(param) = &self.param {
+			write!(f, ", param #{}", param.index)?;
+			if let Some(name) = &param.name {
+				write!(f, " \"{name}\"")?;
+			}
+			write!(f, " with type id {:#04X}", param.type_id)?;
+		}
+		Ok(())
+	}
 }
@@ -5,7 +5,7 @@ use crate::blk::error::ParseError;
 #[derive(Debug, Ord, PartialOrd, Eq, PartialEq, Hash, Copy, Clone, strum::Display)]
 // BLK file type, always first byte of file
 pub enum FileType {
-	/// Unknown and unsupported
+	/// Legacy BBF3 format with integrated name-map
 	BBF           = 0x00,
 	/// BLK file with integrated name-map, no external map is required
 	FAT           = 0x01,
@@ -32,6 +32,11 @@ impl FileType {
 		}
 	}
 
+	/// Reads the file type from the first byte of a file
+	pub fn of_file(file: &[u8]) -> Result<Self, ParseError> {
+		Self::from_byte(*file.first().ok_or(ParseError::EmptyFile)?)
+	}
+
 	pub fn is_slim(&self) -> bool {
 		match self {
 			FileType::SLIM => true,
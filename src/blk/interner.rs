@@ -0,0 +1,157 @@
+use std::{
+	borrow::Borrow,
+	collections::{hash_map::DefaultHasher, HashSet},
+	hash::{Hash, Hasher},
+	mem::size_of,
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		Mutex,
+	},
+};
+
+use crate::blk::blk_type::BlkString;
+
+/// Amount of independently locked sets, such that parallel unpacking rarely contends
+const SHARDS: usize = 16;
+
+/// Heap overhead of a single [`BlkString`] besides its characters, being the reference counts and the inner [`String`]
+const ALLOCATION_OVERHEAD: usize = 2 * size_of::<usize>() + size_of::<String>();
+
+/// Deduplicates names and string values across BLK files, such that equal strings share one allocation.
+/// Set it in [`crate::blk::binary_deserialize::parser::ParseOptions::interner`] to use it while parsing
+#[derive(Debug, Default)]
+pub struct StringInterner {
+	shards:       [Mutex<HashSet<Interned>>; SHARDS],
+	lookups:      AtomicUsize,
+	lookup_bytes: AtomicUsize,
+}
+
+/// Memory used by an interner, compared to allocating each string separately
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct InternerStats {
+	/// Distinct strings held by the interner
+	pub strings:      usize,
+	/// Combined length of the distinct strings
+	pub string_bytes: usize,
+	/// Strings that were requested, including repeated ones
+	pub lookups:      usize,
+	/// Combined length of the requested strings
+	pub lookup_bytes: usize,
+}
+
+impl StringInterner {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Returns the shared allocation of this string, creating it if it is new
+	pub fn intern(&self, s: &str) -> BlkString {
+		self.lookups.fetch_add(1, Ordering::Relaxed);
+		self.lookup_bytes.fetch_add(s.len(), Ordering::Relaxed);
+
+		let mut shard = self.shards[shard_of(s)]
+			.lock()
+			.unwrap_or_else(|poisoned| poisoned.into_inner());
+		if let Some(interned) = shard.get(s) {
+			return interned.0.clone();
+		}
+		let interned = BlkString::new(s.to_owned());
+		shard.insert(Interned(interned.clone()));
+		interned
+	}
+
+	pub fn stats(&self) -> InternerStats {
+		let (strings, string_bytes) = self
+			.shards
+			.iter()
+			.map(|shard| {
+				let shard = shard
+					.lock()
+					.unwrap_or_else(|poisoned| poisoned.into_inner());
+				(shard.len(), shard.iter().map(|s| s.0.len()).sum::<usize>())
+			})
+			.fold((0, 0), |(count, bytes), (c, b)| (count + c, bytes + b));
+		InternerStats {
+			strings,
+			string_bytes,
+			lookups: self.lookups.load(Ordering::Relaxed),
+			lookup_bytes: self.lookup_bytes.load(Ordering::Relaxed),
+		}
+	}
+}
+
+impl InternerStats {
+	/// Allocations that did not happen, because the string already existed
+	pub fn saved_allocations(&self) -> usize {
+		self.lookups.saturating_sub(self.strings)
+	}
+
+	/// Heap memory the distinct strings occupy
+	pub fn interned_bytes(&self) -> usize {
+		self.string_bytes + self.strings * ALLOCATION_OVERHEAD
+	}
+
+	/// Heap memory the strings would have occupied, if each lookup allocated its own
+	pub fn uninterned_bytes(&self) -> usize {
+		self.lookup_bytes + self.lookups * ALLOCATION_OVERHEAD
+	}
+
+	pub fn saved_bytes(&self) -> usize {
+		self.uninterned_bytes()
+			.saturating_sub(self.interned_bytes())
+	}
+}
+
+/// Allows looking up a [`BlkString`] by `&str` without allocating
+#[derive(Debug)]
+struct Interned(BlkString);
+
+impl Borrow<str> for Interned {
+	fn borrow(&self) -> &str {
+		self.0.as_str()
+	}
+}
+
+impl Hash for Interned {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.0.as_str().hash(state)
+	}
+}
+
+impl PartialEq for Interned {
+	fn eq(&self, other: &Self) -> bool {
+		self.0 == other.0
+	}
+}
+
+impl Eq for Interned {}
+
+fn shard_of(s: &str) -> usize {
+	let mut hasher = DefaultHasher::new();
+	s.hash(&mut hasher);
+	hasher.finish() as usize % SHARDS
+}
+
+#[cfg(test)]
+mod test {
+	use std::sync::Arc;
+
+	use crate::blk::interner::StringInterner;
+
+	#[test]
+	fn shares_allocation() {
+		let interner = StringInterner::new();
+		let a = interner.intern("weapons");
+		let b = interner.intern("weapons");
+		let c = interner.intern("mass");
+		assert!(Arc::ptr_eq(&a, &b));
+		assert!(!Arc::ptr_eq(&a, &c));
+
+		let stats = interner.stats();
+		assert_eq!(stats.strings, 2);
+		assert_eq!(stats.string_bytes, "weapons".len() + "mass".len());
+		assert_eq!(stats.lookups, 3);
+		assert_eq!(stats.saved_allocations(), 1);
+		assert!(stats.saved_bytes() > "weapons".len());
+	}
+}
@@ -10,6 +10,11 @@ pub fn uleb128(bytes: &[u8]) -> Result<(usize, usize), ParseError> {
 	// Each bytes leading bit indicates continuation, where the trailing 7 bits for the integer part of the number
 	// This loop might always yield before reaching its last iteration, unless the buffer was cut too early
 	for (i, current) in bytes.iter().enumerate() {
+		// Shifting further would overflow, so the integer cannot be represented
+		if 7 * i >= usize::BITS as usize {
+			return Err(ParseError::UlebOverflow);
+		}
+
 		// The bits holding the integer value, with the leading bit being unset
 		let bits = (current & (MASK - 1)) as usize;
 
@@ -66,4 +71,9 @@ mod test {
 	fn aol_extended() {
 		assert_eq!(uleb128(&[u8::MAX, 42]), Ok((2, 5503)))
 	}
+
+	#[test]
+	fn overflow() {
+		assert_eq!(uleb128(&[u8::MAX; 11]), Err(ParseError::UlebOverflow))
+	}
 }
@@ -9,19 +9,25 @@ use std::{
 
 pub use ::zstd::dict::DecoderDictionary;
 use cfg_if::cfg_if;
-use color_eyre::Report;
-
-use crate::blk::{
-	binary_deserialize::parser::parse_blk,
-	blk_structure::BlkField,
-	blk_type::BlkType,
-	file::FileType,
-	nm_file::NameMap,
-	util::blk_str,
-	zstd::decode_zstd,
+
+use crate::{
+	blk::{
+		binary_deserialize::{
+			bbf::parse_bbf,
+			parser::{parse_blk_with_options, ParseOptions},
+		},
+		blk_structure::BlkField,
+		blk_type::BlkType,
+		error::ParseError,
+		file::FileType,
+		nm_file::NameMap,
+		util::blk_str,
+		zstd::{decode_zstd, BlkScratch},
+	},
+	error::WtBlkError,
 };
 
-/// Decodes flat map of fields into the corresponding nested datastructure
+/// Errors and limits for assembling blocks into their nested datastructure
 mod blk_block_hierarchy;
 
 /// Defines the recursive/nested structure that BLK files are represented with internally
@@ -30,13 +36,15 @@ pub mod blk_structure;
 /// Defines the primitive types that BLK stores
 pub mod blk_type;
 
-/// Shared error that is returned from hot functions,
-/// otherwise, [`color_eyre::Report`] is used
+/// Error returned by the BLK parsers, wrapped by [`crate::error::WtBlkError`] at the API boundary
 pub mod error;
 
 /// One-byte file header that each blk file begins with
 pub mod file;
 
+/// Deduplication of strings shared between BLK files
+pub mod interner;
+
 /// Utility function to decode ULEB128 encoded files
 /// <https://en.wikipedia.org/wiki/LEB128>
 pub mod leb128;
@@ -92,9 +100,19 @@ pub fn unpack_blk(
 	file: &mut Vec<u8>,
 	dictionary: Option<&DecoderDictionary>,
 	nm: Option<Arc<NameMap>>,
-) -> Result<BlkField, Report> {
+) -> Result<BlkField, WtBlkError> {
+	unpack_blk_with_options(file, dictionary, nm, ParseOptions::default())
+}
+
+/// Same as [`unpack_blk`], with custom parsing options
+pub fn unpack_blk_with_options(
+	file: &mut Vec<u8>,
+	dictionary: Option<&DecoderDictionary>,
+	nm: Option<Arc<NameMap>>,
+	options: ParseOptions,
+) -> Result<BlkField, WtBlkError> {
 	let mut offset = 0;
-	let file_type = FileType::from_byte(file[0])?;
+	let file_type = FileType::of_file(file)?;
 	if file_type.is_zstd() {
 		if file_type == FileType::FAT_ZSTD {
 			offset += 1
@@ -105,10 +123,46 @@ pub fn unpack_blk(
 		offset = 1;
 	};
 
-	let parsed = parse_blk(&file[offset..], file_type.is_slim(), nm)?;
+	if file_type == FileType::BBF {
+		return Ok(parse_bbf(&file[offset..])?);
+	}
+	let parsed = parse_blk_with_options(&file[offset..], file_type.is_slim(), nm, options)?;
 	Ok(parsed)
 }
 
+/// Same as [`unpack_blk_with_options`], but leaves the file untouched.
+/// Compressed files are decompressed into the scratch, which is meant to be reused across files
+pub fn unpack_blk_from<'d>(
+	file: &[u8],
+	scratch: &mut BlkScratch<'d>,
+	dictionary: Option<&DecoderDictionary<'d>>,
+	nm: Option<Arc<NameMap>>,
+	options: ParseOptions,
+) -> Result<BlkField, WtBlkError> {
+	let file_type = FileType::of_file(file)?;
+	let unpacked = if file_type.is_zstd() {
+		let decoded = scratch.decode_zstd(file_type, file, dictionary)?;
+		// FAT_ZSTD has a leading byte indicating that its unpacked form is of the FAT format
+		if file_type == FileType::FAT_ZSTD {
+			decoded.get(1..).ok_or(ParseError::EmptyFile)?
+		} else {
+			decoded
+		}
+	} else {
+		&file[1..]
+	};
+
+	if file_type == FileType::BBF {
+		return Ok(parse_bbf(unpacked)?);
+	}
+	Ok(parse_blk_with_options(
+		unpacked,
+		file_type.is_slim(),
+		nm,
+		options,
+	)?)
+}
+
 pub fn make_strict_test() -> BlkField {
 	BlkField::Struct(
 		blk_str("root"),
@@ -1,9 +1,12 @@
 use std::{io::Read, sync::Arc};
 
-use color_eyre::{eyre::ContextCompat, Report};
 use zstd::Decoder;
 
-use crate::blk::{blk_type::BlkString, error::ParseError, leb128::uleb128_offset};
+use crate::{
+	binary_decoder::BinaryDecoder,
+	blk::{blk_type::BlkString, error::ParseError},
+	error::WtBlkError,
+};
 
 #[derive(Clone, Debug)]
 pub struct NameMap {
@@ -16,7 +19,7 @@ impl NameMap {
 		self.parsed.get(idx)
 	}
 
-	pub fn from_encoded_file(file: &[u8]) -> Result<Self, Report> {
+	pub fn from_encoded_file(file: &[u8]) -> Result<Self, WtBlkError> {
 		let decoded = Self::decode_nm_file(file)?;
 
 		let names = Self::parse_slim_nm(&decoded)?;
@@ -27,13 +30,11 @@ impl NameMap {
 		})
 	}
 
-	pub fn decode_nm_file(file: &[u8]) -> Result<Vec<u8>, Report> {
-		let _names_digest = &file.get(0..8).context(format!(
-			"File out of bounds for range 0..8, found len: {}",
-			file.len()
-		))?;
-		let _dict_digest = &file[8..40];
-		let mut zstd_stream = &file[40..];
+	pub fn decode_nm_file(file: &[u8]) -> Result<Vec<u8>, WtBlkError> {
+		let mut header = BinaryDecoder::new(file);
+		let _names_digest = header.next_slice(8).map_err(ParseError::from)?;
+		let _dict_digest = header.next_slice(32).map_err(ParseError::from)?;
+		let mut zstd_stream = header.remaining();
 		let mut decoder = Decoder::new(&mut zstd_stream)?;
 		let mut out = Vec::with_capacity(file.len());
 		let _ = decoder.read_to_end(&mut out)?;
@@ -41,28 +42,39 @@ impl NameMap {
 	}
 
 	pub fn parse_name_section(file: &[u8]) -> Result<Vec<BlkString>, ParseError> {
+		Ok(Self::parse_name_section_with(file, |name| Arc::from(name.to_owned())))
+	}
+
+	/// Same as [`NameMap::parse_name_section`], creating each name through `make`
+	pub fn parse_name_section_with(
+		file: &[u8],
+		mut make: impl FnMut(&str) -> BlkString,
+	) -> Vec<BlkString> {
 		let mut start = 0_usize;
 		let mut names = vec![];
 		for (i, val) in file.iter().enumerate() {
 			if *val == 0 {
-				names.push(Arc::from(String::from_utf8_lossy(&file[start..i].to_owned()).to_string()));
+				names.push(make(&String::from_utf8_lossy(&file[start..i])));
 				start = i + 1;
 			}
 		}
-		Ok(names)
+		names
 	}
 
-	pub fn parse_slim_nm(name_map: &[u8]) -> color_eyre::Result<Vec<BlkString>> {
-		let mut nm_ptr = 0;
+	pub fn parse_slim_nm(name_map: &[u8]) -> Result<Vec<BlkString>, ParseError> {
+		let mut decoder = BinaryDecoder::new(name_map);
 
-		let names_count = uleb128_offset(&name_map[nm_ptr..], &mut nm_ptr)?;
+		let names_count = decoder.next_uleb()?;
 
-		let names_data_size = uleb128_offset(&name_map[nm_ptr..], &mut nm_ptr)?;
+		let names_data_size = decoder.next_uleb()?;
 
-		let names = NameMap::parse_name_section(&name_map[nm_ptr..(nm_ptr + names_data_size)])?;
+		let names = NameMap::parse_name_section(decoder.next_slice(names_data_size)?)?;
 
 		if names_count != names.len() {
-			panic!("Should be equal"); // TODO: Change to result when fn signature allows for it
+			return Err(ParseError::NameCountMismatch {
+				expected: names_count,
+				found:    names.len(),
+			});
 		}
 
 		Ok(names)
@@ -73,7 +85,7 @@ impl NameMap {
 mod test {
 	use std::fs;
 
-	use crate::blk::{leb128::uleb128, nm_file::NameMap};
+	use crate::blk::{error::ParseError, leb128::uleb128, nm_file::NameMap};
 
 	#[test]
 	fn test_any_stream() {
@@ -87,6 +99,18 @@ mod test {
 		)
 	}
 
+	#[test]
+	fn name_count_mismatch() {
+		let nm = [3, 4, b'a', 0, b'b', 0];
+		assert_eq!(
+			NameMap::parse_slim_nm(&nm),
+			Err(ParseError::NameCountMismatch {
+				expected: 3,
+				found:    2,
+			})
+		);
+	}
+
 	#[test]
 	fn test_nm_file() {
 		let file = fs::read("./samples/nm").unwrap();
@@ -1,6 +1,4 @@
-use color_eyre::Report;
-
-use crate::blk::blk_structure::BlkField;
+use crate::{blk::blk_structure::BlkField, error::WtBlkError};
 
 struct Cursor {
 	at:    usize,
@@ -18,7 +16,7 @@ impl Iterator for Cursor {
 }
 
 #[allow(unused)]
-pub fn deserialize_blk(input: &str) -> Result<BlkField, Report> {
+pub fn deserialize_blk(input: &str) -> Result<BlkField, WtBlkError> {
 	let mut c = Cursor {
 		at:    input.chars().count(),
 		inner: input.chars().collect(),
@@ -33,7 +31,7 @@ enum State {
 	ParsingValue,
 }
 
-fn _deserialize_blk(input: &mut Cursor, _parent: &mut BlkField) -> Result<BlkField, Report> {
+fn _deserialize_blk(input: &mut Cursor, _parent: &mut BlkField) -> Result<BlkField, WtBlkError> {
 	let mut typename = String::new();
 	let mut typ = String::new();
 	let mut state = State::Key;
@@ -1,55 +1,135 @@
-use color_eyre::{eyre::bail, Report};
+use std::{io, io::Write};
 
-use crate::blk::blk_structure::BlkField;
+use itertools::Either;
+
+use crate::{blk::blk_structure::BlkField, error::WtBlkError};
 
 impl BlkField {
 	// Public facing formatting fn
-	pub fn as_blk_text(&self) -> Result<String, Report> {
-		self.inner_as_blk_text(&mut 0, true)
+	pub fn as_blk_text(&self) -> Result<String, WtBlkError> {
+		let mut buf = vec![];
+		self.as_blk_text_streaming(&mut buf)?;
+		Ok(String::from_utf8(buf)?)
+	}
+
+	/// Writes the plaintext BLK representation directly into the writer, without buffering it
+	pub fn as_blk_text_streaming(&self, w: &mut impl Write) -> io::Result<()> {
+		self.inner_as_blk_text(w, 0, true)
 	}
 
 	// TODO: Make this generic with a configuration file
 	// Internal fn that actually formats
-	fn inner_as_blk_text(&self, indent_level: &mut usize, is_root: bool) -> Result<String, Report> {
+	fn inner_as_blk_text(
+		&self,
+		w: &mut impl Write,
+		indent_level: usize,
+		is_root: bool,
+	) -> io::Result<()> {
 		match self {
-			BlkField::Value(name, value) => Ok(format!("{name}:{value}")),
+			BlkField::Value(name, value) => {
+				write!(w, "{name}:{value}")?;
+			},
 			BlkField::Struct(name, fields) => {
-				let indent = "\t".repeat(*indent_level);
-				*indent_level += 1;
-				let children = fields
-					.iter()
-					.map(|x| {
-						Ok(format!(
-							"{indent}{}",
-							x.inner_as_blk_text(indent_level, false)?
-						))
-					})
-					.collect::<Result<Vec<_>, Report>>()?
-					.join("\n");
-				*indent_level -= 1;
-
-				let indent_closing = "\t".repeat(indent_level.saturating_sub(1));
-				Ok(if is_root {
-					format!("{children}")
+				if !is_root {
+					writeln!(w, "{name} {{")?;
+				}
+
+				// Merged arrays have no equivalent in BLK, so they are expanded into their original repeated keys
+				let children = if fields.iter().any(|x| matches!(x, BlkField::Merged(..))) {
+					Either::Left(BlkField::unmerged_order(fields).into_iter().map(|(i, j)| {
+						match &fields[i] {
+							BlkField::Merged(_, values, _) => &values[j],
+							field => field,
+						}
+					}))
 				} else {
-					format!("{name} {{\n{children}\n{indent_closing}}}")
-				})
+					Either::Right(fields.iter())
+				};
+
+				let mut is_first = true;
+				for child in children {
+					if !is_first {
+						w.write_all(b"\n")?;
+					}
+					write_indent(w, indent_level)?;
+					child.inner_as_blk_text(w, indent_level + 1, false)?;
+					is_first = false;
+				}
+
+				if !is_root {
+					w.write_all(b"\n")?;
+					write_indent(w, indent_level.saturating_sub(1))?;
+					w.write_all(b"}")?;
+				}
 			},
-			BlkField::Merged(..) => {
-				bail!("Attempted to parse merged array in blk-text function (array type is not available in the BLK format)")
+			BlkField::Merged(_, values, _) => {
+				let mut is_first = true;
+				for value in values {
+					if !is_first {
+						w.write_all(b"\n")?;
+					}
+					value.inner_as_blk_text(w, indent_level, is_root)?;
+					is_first = false;
+				}
 			},
 		}
+		Ok(())
 	}
 }
 
+fn write_indent(w: &mut impl Write, indent_level: usize) -> io::Result<()> {
+	for _ in 0..indent_level {
+		w.write_all(b"\t")?;
+	}
+	Ok(())
+}
+
 #[cfg(test)]
 mod test {
-	use crate::blk::make_strict_test;
+	use crate::blk::{blk_structure::BlkField, blk_type::BlkType, make_strict_test, util::blk_str};
 
 	#[test]
 	fn test_expected() {
 		// For testing purposes i should probably make a better way for this
 		let root = make_strict_test();
-		println!("{}", root.inner_as_blk_text(&mut 0, true).unwrap());
+		println!("{}", root.as_blk_text().unwrap());
+	}
+
+	#[test]
+	fn streaming() {
+		let root = make_strict_test();
+		let mut buf = vec![];
+		root.as_blk_text_streaming(&mut buf).unwrap();
+		assert_eq!(
+			String::from_utf8(buf).unwrap(),
+			"vec4f:p4 = 1.25, 2.5, 5, 10\n\
+			 int:i = 42\n\
+			 long:i64 = 64\n\
+			 alpha {\n\
+			 \tstr:t = \"hello\"\n\
+			 \tbool:b = true\n\
+			 \tcolor:c = 1, 2, 3, 4\n\
+			 \tgamma {\n\
+			 \t\tvec2i:ip2 = 3, 4\n\
+			 \t\tvec2f:p2 = 1.25, 2.5\n\
+			 \t\ttransform:m = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 1.25, 2.5, 5.0]\n\
+			 \t}\n\
+			 }\n\
+			 beta {\n\
+			 \tfloat:r = 1.25\n\
+			 \tvec2i:ip2 = 1, 2\n\
+			 \tvec3f:p3 = 1.25, 2.5, 5\n\
+			 }"
+		);
+	}
+
+	#[test]
+	fn merged_as_repeated_keys() {
+		let mut root = make_strict_test();
+		root.insert_field(BlkField::Value(blk_str("int"), BlkType::Int(420)))
+			.unwrap();
+		let expected = root.as_blk_text().unwrap();
+		root.merge_fields();
+		assert_eq!(root.as_blk_text().unwrap(), expected);
 	}
 }
@@ -1,9 +1,24 @@
-use std::{collections::HashMap, io::Write, mem, sync::Arc};
+use std::{
+	collections::{HashMap, HashSet},
+	io,
+	io::Write,
+	mem,
+};
 
-use color_eyre::Report;
-use serde_json::ser::{Formatter, PrettyFormatter};
+use fallible_iterator::FallibleIterator;
+use serde::Serializer;
+use serde_json::{ser::Formatter, Serializer as JsonSerializer};
 
-use crate::blk::{blk_structure::BlkField, blk_type::BlkString};
+use crate::{
+	blk::{
+		binary_deserialize::events::BlkEvent,
+		blk_structure::BlkField,
+		blk_type::{BlkString, BlkType},
+		error::ParseError,
+		plaintext_serialize::json_format::{DuplicateKeys, JsonFormat, JsonFormatter},
+	},
+	error::WtBlkError,
+};
 
 impl BlkField {
 	/// Merges duplicate keys in struct fields into the Merged array variant
@@ -37,113 +52,282 @@ impl BlkField {
 				.for_each(|(key, indexes)| {
 					let first_element = indexes[0];
 					let to_merge = indexes
-						.into_iter()
-						.map(|e| old[e].take().expect("Infallible"))
+						.iter()
+						.map(|&e| old[e].take().expect("Infallible"))
 						.collect();
-					old[first_element] = Some(BlkField::Merged(Arc::from(key), to_merge));
+					old[first_element] = Some(BlkField::Merged(key, to_merge, indexes));
 				});
 			*fields = old.into_iter().filter_map(|e| e).collect();
 		}
 	}
 
-	pub fn as_serde_json(&self) -> Result<Vec<u8>, Report> {
+	/// Inverse of [`BlkField::merge_fields`], moving the elements of merged arrays back to their original positions
+	pub fn unmerge_fields(&mut self) {
+		if let BlkField::Struct(_, fields) = self {
+			let order = BlkField::unmerged_order(fields);
+
+			let mut old = mem::take(fields)
+				.into_iter()
+				.map(|field| match field {
+					BlkField::Merged(_, values, _) => values.into_iter().map(Some).collect(),
+					field => vec![Some(field)],
+				})
+				.collect::<Vec<Vec<_>>>();
+
+			*fields = order
+				.into_iter()
+				.map(|(i, j)| old[i][j].take().expect("Infallible"))
+				.collect();
+
+			// Recurse last, as merged elements only become regular fields above
+			for field in fields.iter_mut() {
+				field.unmerge_fields();
+			}
+		}
+	}
+
+	pub fn as_serde_json(&self) -> io::Result<Vec<u8>> {
 		let mut res = vec![];
 		self.as_serde_json_streaming(&mut res)?;
 		Ok(res)
 	}
 
-	pub fn as_serde_json_string(&self) -> Result<String, Report> {
+	pub fn as_serde_json_string(&self) -> Result<String, WtBlkError> {
 		let mut res = vec![];
 		self.as_serde_json_streaming(&mut res)?;
 		Ok(String::from_utf8(res)?)
 	}
 
-	pub fn as_serde_json_streaming(&self, w: &mut impl Write) -> Result<(), Report> {
-		// let mut ser = PrettyFormatter::with_indent(b"\t");
-		let mut ser = PrettyFormatter::new();
-		self._as_serde_json_streaming(w, &mut ser, true, true, false)?;
-		Ok(())
+	pub fn as_serde_json_streaming(&self, w: &mut impl Write) -> io::Result<()> {
+		self.as_serde_json_streaming_with(w, &JsonFormat::default())
 	}
 
-	fn _as_serde_json_streaming(
+	/// Same as [`BlkField::as_serde_json_streaming`], with custom formatting options.
+	/// With [`DuplicateKeys::Merge`], duplicates are merged on a copy unless [`BlkField::merge_fields`] was already applied
+	pub fn as_serde_json_streaming_with(
 		&self,
 		w: &mut impl Write,
-		ser: &mut PrettyFormatter,
-		is_root: bool,
-		is_first: bool,
-		in_merging_array: bool,
-	) -> Result<(), Report> {
+		format: &JsonFormat,
+	) -> io::Result<()> {
+		let mut writer = JsonEventWriter::new(w, *format);
+		if format.duplicates == DuplicateKeys::Merge && self.has_unmerged_duplicates() {
+			let mut merged = self.clone();
+			merged.merge_fields();
+			return merged.write_json(&mut writer);
+		}
+		self.write_json(&mut writer)
+	}
+
+	/// Whether [`BlkField::merge_fields`] would change anything
+	fn has_unmerged_duplicates(&self) -> bool {
 		match self {
-			BlkField::Value(k, v) => {
-				if !in_merging_array {
-					ser.begin_object_key(w, is_first)?;
-					ser.begin_string(w)?;
-					ser.write_string_fragment(w, k.as_ref())?;
-					ser.end_string(w)?;
-					ser.end_object_key(w)?;
-
-					ser.begin_object_value(w)?;
-				}
-				v.serialize_streaming(w, ser)?;
+			BlkField::Struct(_, fields) => {
+				has_duplicate_keys(fields) || fields.iter().any(BlkField::has_unmerged_duplicates)
+			},
+			_ => false,
+		}
+	}
 
-				if !in_merging_array {
-					ser.end_object_value(w)?;
+	fn write_json<W: Write>(&self, writer: &mut JsonEventWriter<W>) -> io::Result<()> {
+		match self {
+			BlkField::Value(k, v) => writer.value(k, v)?,
+			// Duplicate keys cannot be represented in an object, so they are written as a list of pairs instead
+			BlkField::Struct(k, v)
+				if writer.format.duplicates == DuplicateKeys::Pairs && has_duplicate_keys(v) =>
+			{
+				writer.begin_array(k)?;
+				for value in v {
+					writer.begin_array("")?;
+					writer.string(value.get_name().as_str())?;
+					value.write_json(writer)?;
+					writer.end_array()?;
 				}
+				writer.end_array()?;
 			},
 			BlkField::Struct(k, v) => {
-				// Skip over object key when root or merging an array
-				if !is_root && !in_merging_array {
-					ser.begin_object_key(w, is_first)?;
-					ser.begin_string(w)?;
-					ser.write_string_fragment(w, k.as_ref())?;
-					ser.end_string(w)?;
-					ser.end_object_key(w)?;
-					ser.begin_object_value(w)?;
-				}
-				// Empty objects should not have newlines in them, so we simply skip them
-				if !v.is_empty() {
-					ser.begin_object(w)?;
-					let mut is_first = true;
-					for value in v {
-						value._as_serde_json_streaming(w, ser, false, is_first, false)?;
-						is_first = false;
-					}
-					ser.end_object_value(w)?;
-					ser.end_object(w)?;
-
-				// Instead we just write brackets without a newline in them
-				} else {
-					ser.write_string_fragment(w, "{}")?;
+				writer.begin_block(k)?;
+				for value in v {
+					value.write_json(writer)?;
 				}
+				writer.end_block()?;
 			},
-			BlkField::Merged(k, v) => {
-				ser.begin_object_key(w, is_first)?;
-				ser.begin_string(w)?;
-				ser.write_string_fragment(w, k.as_ref())?;
-				ser.end_string(w)?;
-				ser.end_object_key(w)?;
-				ser.begin_object_value(w)?;
-
-				ser.begin_array(w)?;
-				let mut is_first = true;
+			BlkField::Merged(k, v, _) => {
+				writer.begin_array(k)?;
 				for value in v {
-					ser.begin_array_value(w, is_first)?;
-					value._as_serde_json_streaming(w, ser, false, is_first, true)?;
-					is_first = false;
-					ser.end_array_value(w)?;
+					value.write_json(writer)?;
 				}
-				ser.end_array(w)?;
+				writer.end_array()?;
 			},
 		}
 		Ok(())
 	}
 }
 
+/// Writes Json from a sequence of [`BlkEvent`]s, such that a BLK never has to be held in memory as a whole.
+/// Keys that occur more than once are written as they are, as merging them requires the entire block
+pub struct JsonEventWriter<W: Write> {
+	w:      W,
+	ser:    JsonFormatter<'static>,
+	format: JsonFormat,
+	/// Objects and arrays that are currently open, innermost last
+	open:   Vec<Container>,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Container {
+	is_array: bool,
+	is_empty: bool,
+}
+
+impl<W: Write> JsonEventWriter<W> {
+	pub fn new(w: W, format: JsonFormat) -> Self {
+		Self {
+			w,
+			ser: format.formatter(),
+			format,
+			open: vec![],
+		}
+	}
+
+	pub fn write_event(&mut self, event: &BlkEvent) -> io::Result<()> {
+		match event {
+			BlkEvent::BeginBlock(name) => self.begin_block(name),
+			BlkEvent::Value(name, value) => self.value(name, value),
+			BlkEvent::EndBlock => self.end_block(),
+		}
+	}
+
+	/// Writes all remaining events, such as those of a [`crate::blk::binary_deserialize::events::BlkEventReader`]
+	pub fn write_events(
+		&mut self,
+		mut events: impl FallibleIterator<Item = BlkEvent, Error = ParseError>,
+	) -> Result<(), WtBlkError> {
+		while let Some(event) = events.next()? {
+			self.write_event(&event)?;
+		}
+		Ok(())
+	}
+
+	pub fn into_inner(self) -> W {
+		self.w
+	}
+
+	// Writes the key or array separator preceding an element of the innermost container
+	fn begin_element(&mut self, key: &str) -> io::Result<()> {
+		if let Some(container) = self.open.last_mut() {
+			let is_first = mem::replace(&mut container.is_empty, false);
+			if container.is_array {
+				self.ser.begin_array_value(&mut self.w, is_first)?;
+			} else {
+				write_key(&mut self.w, &mut self.ser, key, is_first)?;
+			}
+		}
+		Ok(())
+	}
+
+	fn end_element(&mut self) -> io::Result<()> {
+		match self.open.last() {
+			Some(container) if container.is_array => self.ser.end_array_value(&mut self.w)?,
+			Some(_) => self.ser.end_object_value(&mut self.w)?,
+			None => {},
+		}
+		Ok(())
+	}
+
+	fn begin_block(&mut self, name: &str) -> io::Result<()> {
+		self.begin_element(name)?;
+		self.ser.begin_object(&mut self.w)?;
+		self.open.push(Container {
+			is_array: false,
+			is_empty: true,
+		});
+		Ok(())
+	}
+
+	fn end_block(&mut self) -> io::Result<()> {
+		if self.open.pop().is_none_or(|container| container.is_array) {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidInput,
+				"Block ended without having begun",
+			));
+		}
+		self.ser.end_object(&mut self.w)?;
+		self.end_element()
+	}
+
+	fn begin_array(&mut self, name: &str) -> io::Result<()> {
+		self.begin_element(name)?;
+		self.ser.begin_array(&mut self.w)?;
+		self.open.push(Container {
+			is_array: true,
+			is_empty: true,
+		});
+		Ok(())
+	}
+
+	fn end_array(&mut self) -> io::Result<()> {
+		self.open.pop();
+		self.ser.end_array(&mut self.w)?;
+		self.end_element()
+	}
+
+	fn value(&mut self, name: &str, value: &BlkType) -> io::Result<()> {
+		self.begin_element(name)?;
+		value.serialize_streaming(&mut self.w, &mut self.ser, &self.format)?;
+		self.end_element()
+	}
+
+	fn string(&mut self, s: &str) -> io::Result<()> {
+		self.begin_element("")?;
+		JsonSerializer::new(&mut self.w).serialize_str(s)?;
+		self.end_element()
+	}
+}
+
+fn write_key(
+	w: &mut impl Write,
+	ser: &mut JsonFormatter,
+	key: &str,
+	is_first: bool,
+) -> io::Result<()> {
+	ser.begin_object_key(w, is_first)?;
+	ser.begin_string(w)?;
+	ser.write_string_fragment(w, key)?;
+	ser.end_string(w)?;
+	ser.end_object_key(w)?;
+	ser.begin_object_value(w)?;
+	Ok(())
+}
+
+fn has_duplicate_keys(fields: &[BlkField]) -> bool {
+	let mut seen = HashSet::with_capacity(fields.len());
+	!fields.iter().all(|field| seen.insert(field.get_name()))
+}
+
 #[cfg(test)]
 mod test {
 	use std::fs;
 
-	use crate::blk::{blk_structure::BlkField, blk_type::BlkType, make_strict_test, util::blk_str};
+	use crate::blk::{
+		binary_deserialize::{
+			events::{BlkEvent, BlkEventReader},
+			parser::parse_blk,
+		},
+		blk_structure::BlkField,
+		blk_type::BlkType,
+		make_strict_test,
+		plaintext_serialize::{
+			json::JsonEventWriter,
+			json_format::{ColorFormat, DuplicateKeys, FloatFormat, JsonFormat, MatrixFormat},
+		},
+		util::blk_str,
+	};
+
+	fn format_with(blk: &BlkField, format: JsonFormat) -> String {
+		let mut buf = vec![];
+		blk.as_serde_json_streaming_with(&mut buf, &format).unwrap();
+		String::from_utf8(buf).unwrap()
+	}
 
 	#[test]
 	fn streaming() {
@@ -172,6 +356,54 @@ mod test {
 		);
 	}
 
+	#[test]
+	fn merges_when_serializing() {
+		let mut blk = make_strict_test();
+		blk.insert_field(BlkField::Value(blk_str("int"), BlkType::Int(420)))
+			.unwrap();
+		let unmerged = blk.clone();
+		blk.merge_fields();
+		assert_eq!(
+			format_with(&unmerged, JsonFormat::default()),
+			fs::read_to_string("./samples/expected_merged.json").unwrap()
+		);
+		assert_eq!(
+			format_with(&unmerged, JsonFormat::default()),
+			format_with(&blk, JsonFormat::default())
+		);
+	}
+
+	#[test]
+	fn unmerge_inverts_merge() {
+		let mut expected = make_strict_test();
+		expected
+			.insert_field(BlkField::Value(blk_str("int"), BlkType::Int(420)))
+			.unwrap();
+		expected
+			.insert_field(BlkField::Value(blk_str("unrelated"), BlkType::Bool(false)))
+			.unwrap();
+		expected
+			.insert_field(BlkField::new_struct(blk_str("alpha")))
+			.unwrap();
+		expected
+			.insert_field(BlkField::Value(blk_str("int"), BlkType::Int(7)))
+			.unwrap();
+		if let BlkField::Struct(_, fields) = &mut expected {
+			fields[3]
+				.insert_field(BlkField::Value(
+					blk_str("str"),
+					BlkType::Str(blk_str("again")),
+				))
+				.unwrap();
+		}
+
+		let mut blk = expected.clone();
+		blk.merge_fields();
+		assert_ne!(blk, expected);
+		blk.unmerge_fields();
+		assert_eq!(blk, expected);
+	}
+
 	#[test]
 	fn streaming_empty() {
 		let blk = BlkField::new_root();
@@ -186,4 +418,151 @@ mod test {
 		let s = sample.as_serde_json_string().unwrap();
 		assert_eq!(s, fs::read_to_string("./samples/expected.json").unwrap());
 	}
+
+	#[test]
+	fn compact() {
+		let mut blk = BlkField::new_root();
+		blk.insert_field(BlkField::Value(blk_str("int"), BlkType::Int(42)))
+			.unwrap();
+		let mut inner = BlkField::new_struct(blk_str("inner"));
+		inner
+			.insert_field(BlkField::Value(blk_str("float"), BlkType::Float(1.25)))
+			.unwrap();
+		blk.insert_field(inner).unwrap();
+		blk.insert_field(BlkField::new_struct(blk_str("empty")))
+			.unwrap();
+		assert_eq!(
+			format_with(&blk, JsonFormat::compact()),
+			r#"{"int":42,"inner":{"float":1.25},"empty":{}}"#
+		);
+	}
+
+	#[test]
+	fn tab_indent() {
+		let blk = make_strict_test();
+		let expected = fs::read_to_string("./samples/expected.json")
+			.unwrap()
+			.lines()
+			.map(|line| {
+				let trimmed = line.trim_start_matches(' ');
+				"\t".repeat((line.len() - trimmed.len()) / 2) + trimmed
+			})
+			.collect::<Vec<_>>()
+			.join("\n");
+		let format = JsonFormat {
+			indent: "\t",
+			..JsonFormat::default()
+		};
+		assert_eq!(format_with(&blk, format), expected);
+	}
+
+	#[test]
+	fn value_options() {
+		let mut blk = BlkField::new_root();
+		blk.insert_field(BlkField::Value(blk_str("float"), BlkType::Float(1.0)))
+			.unwrap();
+		blk.insert_field(BlkField::Value(
+			blk_str("color"),
+			BlkType::Color {
+				r: 255,
+				g: 16,
+				b: 1,
+				a: 0,
+			},
+		))
+		.unwrap();
+		blk.insert_field(BlkField::Value(
+			blk_str("transform"),
+			BlkType::Float12(Box::new([
+				1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 1.25, 2.5, 5.0,
+			])),
+		))
+		.unwrap();
+
+		assert_eq!(
+			format_with(&blk, JsonFormat::compact()),
+			r#"{"float":1.0,"color":[255,16,1,0],"transform":[[1.0,0.0,0.0],[0.0,1.0,0.0],[0.0,0.0,1.0],[1.25,2.5,5.0]]}"#
+		);
+
+		let format = JsonFormat {
+			float: FloatFormat::Fixed(2),
+			matrix: MatrixFormat::Flat,
+			color: ColorFormat::Hex,
+			..JsonFormat::compact()
+		};
+		assert_eq!(
+			format_with(&blk, format),
+			r##"{"float":1.00,"color":"#FF100100","transform":[1.00,0.00,0.00,0.00,1.00,0.00,0.00,0.00,1.00,1.25,2.50,5.00]}"##
+		);
+
+		let mut blk = BlkField::new_root();
+		for v in [1.0 / 30.0, 0.05, 5.0, 1000.25] {
+			blk.insert_field(BlkField::Value(blk_str("float"), BlkType::Float(v)))
+				.unwrap();
+		}
+		let format = JsonFormat {
+			float: FloatFormat::Rounded(4),
+			..JsonFormat::compact()
+		};
+		assert_eq!(
+			format_with(&blk, format),
+			r#"{"float":[0.0333,0.05,5.0,1000.25]}"#
+		);
+	}
+
+	#[test]
+	fn duplicate_pairs() {
+		let mut blk = BlkField::new_root();
+		let mut inner = BlkField::new_struct(blk_str("inner"));
+		inner
+			.insert_field(BlkField::Value(blk_str("unique"), BlkType::Bool(true)))
+			.unwrap();
+		blk.insert_field(BlkField::Value(blk_str("int"), BlkType::Int(42)))
+			.unwrap();
+		blk.insert_field(inner).unwrap();
+		blk.insert_field(BlkField::Value(blk_str("int"), BlkType::Int(420)))
+			.unwrap();
+
+		let format = JsonFormat {
+			duplicates: DuplicateKeys::Pairs,
+			..JsonFormat::compact()
+		};
+		assert_eq!(
+			format_with(&blk, format),
+			r#"[["int",42],["inner",{"unique":true}],["int",420]]"#
+		);
+	}
+
+	#[test]
+	fn events_match_tree() {
+		let file = fs::read("./samples/section_fat.blk").unwrap();
+		let events = BlkEventReader::new(&file[1..], false, None).unwrap();
+		let mut writer = JsonEventWriter::new(vec![], JsonFormat::default());
+		writer.write_events(events).unwrap();
+
+		let tree = parse_blk(&file[1..], false, None).unwrap();
+		assert_eq!(
+			String::from_utf8(writer.into_inner()).unwrap(),
+			format_with(&tree, JsonFormat::default())
+		);
+	}
+
+	#[test]
+	fn events_keep_duplicates() {
+		let mut writer = JsonEventWriter::new(vec![], JsonFormat::compact());
+		for event in [
+			BlkEvent::BeginBlock(blk_str("root")),
+			BlkEvent::Value(blk_str("int"), BlkType::Int(42)),
+			BlkEvent::BeginBlock(blk_str("inner")),
+			BlkEvent::EndBlock,
+			BlkEvent::Value(blk_str("int"), BlkType::Int(420)),
+			BlkEvent::EndBlock,
+		] {
+			writer.write_event(&event).unwrap();
+		}
+		assert_eq!(
+			String::from_utf8(writer.into_inner()).unwrap(),
+			r#"{"int":42,"inner":{},"int":420}"#
+		);
+	}
 }
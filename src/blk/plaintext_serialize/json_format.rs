@@ -0,0 +1,147 @@
+use std::io::{self, Write};
+
+use serde_json::ser::{CompactFormatter, Formatter, PrettyFormatter};
+
+/// Options for formatting BLK into Json
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct JsonFormat {
+	/// Writes newlines and indentation, otherwise the entire output is a single line
+	pub pretty:     bool,
+	/// Indentation used for each level of nesting, only applies when `pretty` is set
+	pub indent:     &'static str,
+	pub float:      FloatFormat,
+	pub matrix:     MatrixFormat,
+	pub color:      ColorFormat,
+	pub duplicates: DuplicateKeys,
+}
+
+impl Default for JsonFormat {
+	fn default() -> Self {
+		Self {
+			pretty:     true,
+			indent:     "  ",
+			float:      FloatFormat::Shortest,
+			matrix:     MatrixFormat::Nested,
+			color:      ColorFormat::Array,
+			duplicates: DuplicateKeys::Merge,
+		}
+	}
+}
+
+impl JsonFormat {
+	/// Reproduces the `.blkx` files of the community datamine byte for byte
+	/// <https://github.com/gszabi99/War-Thunder-Datamine>.
+	/// Floats are rounded to four decimal places, and duplicates are merged into an array at their first occurrence
+	pub const GSZABI_REPO: Self = Self {
+		pretty:     true,
+		indent:     "  ",
+		float:      FloatFormat::Rounded(4),
+		matrix:     MatrixFormat::Nested,
+		color:      ColorFormat::Array,
+		duplicates: DuplicateKeys::Merge,
+	};
+
+	/// Single line output with otherwise default options
+	pub fn compact() -> Self {
+		Self {
+			pretty: false,
+			..Self::default()
+		}
+	}
+
+	pub(crate) fn formatter(&self) -> JsonFormatter<'static> {
+		if self.pretty {
+			JsonFormatter::Pretty(PrettyFormatter::with_indent(self.indent.as_bytes()))
+		} else {
+			JsonFormatter::Compact(CompactFormatter)
+		}
+	}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum FloatFormat {
+	/// Shortest representation that parses back into the same value, such as `1.25` or `5.0`
+	Shortest,
+	/// Fixed amount of digits after the decimal point
+	Fixed(u8),
+	/// Rounded to at most this many digits after the decimal point, otherwise the same as [`FloatFormat::Shortest`]
+	Rounded(u8),
+}
+
+/// Shape of the 3x4 transformation matrix ([`crate::blk::blk_type::BlkType::Float12`])
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MatrixFormat {
+	/// Four arrays of three elements
+	Nested,
+	/// One array of all twelve elements
+	Flat,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ColorFormat {
+	/// Array of `[r, g, b, a]`
+	Array,
+	/// String in the form of `#RRGGBBAA`
+	Hex,
+}
+
+/// Handling of keys that occur more than once in the same block
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum DuplicateKeys {
+	/// Duplicates are merged into one array, see [`crate::blk::blk_structure::BlkField::merge_fields`]
+	Merge,
+	/// Blocks containing duplicates are written as an array of `[key, value]` pairs, retaining order
+	Pairs,
+}
+
+impl FloatFormat {
+	pub(crate) fn write<W: Write + ?Sized>(self, w: &mut W, v: f32) -> io::Result<()> {
+		match self {
+			FloatFormat::Shortest => {
+				let mut buf = ryu::Buffer::new();
+				w.write_all(buf.format(v).as_bytes())
+			},
+			FloatFormat::Fixed(digits) => write!(w, "{:.*}", digits as usize, v),
+			FloatFormat::Rounded(digits) => {
+				let scale = 10_f64.powi(digits as i32);
+				let mut buf = ryu::Buffer::new();
+				w.write_all(buf.format((v as f64 * scale).round() / scale).as_bytes())
+			},
+		}
+	}
+}
+
+/// Either of the serde formatters, selected at runtime by [`JsonFormat::pretty`]
+pub(crate) enum JsonFormatter<'a> {
+	Pretty(PrettyFormatter<'a>),
+	Compact(CompactFormatter),
+}
+
+macro_rules! delegate {
+	($($name:ident($($arg:ident: $t:ty),*);)*) => {
+		$(
+			#[inline]
+			fn $name<W: ?Sized + Write>(&mut self, w: &mut W $(, $arg: $t)*) -> io::Result<()> {
+				match self {
+					JsonFormatter::Pretty(f) => f.$name(w $(, $arg)*),
+					JsonFormatter::Compact(f) => f.$name(w $(, $arg)*),
+				}
+			}
+		)*
+	};
+}
+
+impl Formatter for JsonFormatter<'_> {
+	delegate! {
+		begin_array();
+		end_array();
+		begin_array_value(first: bool);
+		end_array_value();
+		begin_object();
+		end_object();
+		begin_object_key(first: bool);
+		end_object_key();
+		begin_object_value();
+		end_object_value();
+	}
+}
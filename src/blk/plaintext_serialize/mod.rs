@@ -3,3 +3,5 @@
 mod blockfile;
 /// Formats BLK to Json
 pub mod json;
+/// Options for the Json output
+pub mod json_format;
@@ -2,31 +2,52 @@ use std::{fs, sync::Arc, time::Instant};
 
 use zstd::dict::DecoderDictionary;
 
-use crate::blk::{
-	binary_deserialize::parser::parse_blk,
-	file::FileType,
-	make_strict_test,
-	nm_file::NameMap,
-	zstd::decode_zstd,
+use crate::{
+	blk::{
+		binary_deserialize::parser::{parse_blk, parse_blk_with_options, BlkSections, ParseOptions},
+		blk_type::{BlkType, UnknownValue},
+		error::{BlkSection, ErrorLocation, ParamLocation, ParseError},
+		file::FileType,
+		make_strict_test,
+		nm_file::NameMap,
+		plaintext_serialize::json_format::JsonFormat,
+		unpack_blk,
+		unpack_blk_from,
+		util::blk_str,
+		zstd::{decode_zstd, BlkScratch},
+	},
+	error::WtBlkError,
 };
 
-// #[test]
-// fn json_parity() {
-// 	let nm = fs::read("./samples/rendist/nm").unwrap();
-// 	let dict = fs::read("./samples/rendist/ca35013aabca60792d5203b0137d0a8720d1dc151897eb856b12318891d08466.dict").unwrap();
-// 	let frame_decoder = DecoderDictionary::copy(&dict);
-//
-// 	let mut file = fs::read("./samples/su_r_27er.blk").unwrap();
-// 	file = decode_zstd(&file, Arc::new(frame_decoder)).unwrap();
-// 	let shared_name_map = NameMap::from_encoded_file(&nm).unwrap();
-// 	let output = parse_blk(&file, true, Arc::new(shared_name_map));
-// 	assert_eq!(
-// 		output
-// 			.unwrap()
-// 			.as_ref_json(FormattingConfiguration::GSZABI_REPO),
-// 		fs::read_to_string("./samples/su_r_27er.blkx").unwrap()
-// 	)
-// }
+#[test]
+fn json_parity() {
+	// Same as the rendist name map, which stems from a newer client, except for the one parameter of su_r_27er.blk it renamed
+	let nm = fs::read("./samples/su_r_27er.nm").unwrap();
+	let dict = fs::read(
+		"./samples/rendist/ca35013aabca60792d5203b0137d0a8720d1dc151897eb856b12318891d08466.dict",
+	)
+	.unwrap();
+	let frame_decoder = DecoderDictionary::copy(&dict);
+
+	let shared_name_map = NameMap::from_encoded_file(&nm).unwrap();
+
+	let mut file = fs::read("./samples/su_r_27er.blk").unwrap();
+	let output = unpack_blk(
+		&mut file,
+		Some(&frame_decoder),
+		Some(Arc::new(shared_name_map)),
+	)
+	.unwrap();
+
+	let mut buf = vec![];
+	output
+		.as_serde_json_streaming_with(&mut buf, &JsonFormat::GSZABI_REPO)
+		.unwrap();
+	assert_eq!(
+		String::from_utf8(buf).unwrap(),
+		fs::read_to_string("./samples/su_r_27er.blkx").unwrap()
+	)
+}
 
 #[test]
 fn fat_blk() {
@@ -43,6 +64,150 @@ fn netfile() {
 	assert_eq!(output.pointer("slogan").unwrap().value().unwrap().to_string(), "t = \"○ Warriors at the gate ○\"")
 }
 
+#[test]
+fn error_location() {
+	let file = fs::read("./samples/section_fat.blk").unwrap();
+	let mut file = file[1..].to_vec();
+	let params_info = BlkSections::split(&file, false)
+		.unwrap()
+		.param_location(0)
+		.offset;
+	// Type id of the first parameter, "vec4f"
+	file[params_info + 3] = 0xFF;
+
+	let err = parse_blk(&file, false, None).unwrap_err();
+	assert_eq!(err.kind(), &ParseError::BadBlkValue);
+	assert_eq!(
+		err.location(),
+		Some(&ErrorLocation {
+			offset:  params_info,
+			section: BlkSection::ParamsInfo,
+			param:   Some(ParamLocation {
+				index:   0,
+				name:    Some(blk_str("vec4f")),
+				type_id: 0xFF,
+			}),
+		})
+	);
+	assert_eq!(
+		err.to_string(),
+		format!("Blk value parsing failed (at byte {params_info} in params info, param #0 \"vec4f\" with type id 0xFF)")
+	);
+}
+
+#[test]
+fn unknown_type() {
+	let file = fs::read("./samples/section_fat.blk").unwrap();
+	let mut file = file[1..].to_vec();
+	let params_info = BlkSections::split(&file, false)
+		.unwrap()
+		.param_location(0)
+		.offset;
+	file[params_info + 3] = 0x1F;
+	let raw: [u8; 4] = file[params_info + 4..params_info + 8].try_into().unwrap();
+
+	assert!(parse_blk(&file, false, None).is_err());
+	let output = parse_blk_with_options(
+		&file,
+		false,
+		None,
+		ParseOptions {
+			keep_unknown_types: true,
+			..Default::default()
+		},
+	)
+	.unwrap();
+	// The data region holds the floats of vec4f, up to where the next value begins
+	let data = [1.25_f32, 2.5, 5.0, 10.0]
+		.iter()
+		.flat_map(|f| f.to_le_bytes())
+		.collect::<Vec<_>>();
+	assert_eq!(
+		output.pointer("vec4f").unwrap().value(),
+		Some(&BlkType::Unknown(Box::new(UnknownValue {
+			type_id: 0x1F,
+			raw,
+			data,
+		})))
+	);
+	let json = output.as_serde_json_string().unwrap();
+	assert!(json.contains(&format!(
+		"\"0x1F:{:08X}:0000A03F00002040",
+		u32::from_be_bytes(raw)
+	)));
+}
+
+/// Inputs that used to panic the parser, each has to be rejected with an error instead.
+/// They follow the layout of the `parse_blk` fuzz target
+#[test]
+fn fuzz_regressions() {
+	let names = fs::read("./samples/names").unwrap();
+	let shared_name_map = Arc::new(NameMap {
+		parsed: Arc::new(NameMap::parse_slim_nm(&names).unwrap()),
+		binary: names,
+	});
+
+	for entry in fs::read_dir("./samples/fuzz/parse_blk").unwrap() {
+		let path = entry.unwrap().path();
+		let file = fs::read(&path).unwrap();
+		let (kind, file) = file.split_first().unwrap();
+		assert!(
+			parse_blk(file, kind & 1 == 1, Some(shared_name_map.clone())).is_err(),
+			"{} did not fail",
+			path.display()
+		);
+	}
+}
+
+#[test]
+fn unpack_from_slice() {
+	let nm = Arc::new(NameMap::from_encoded_file(&fs::read("./samples/nm").unwrap()).unwrap());
+	let dict = fs::read(
+		"./samples/bfb732560ad45234690acad246d7b14c2f25ad418a146e5e7ef68ba3386a315c.dict",
+	)
+	.unwrap();
+	let frame_decoder = DecoderDictionary::copy(&dict);
+	let mut scratch = BlkScratch::new();
+
+	// The same scratch is reused for every file, none of which are modified
+	for (sample, dictionary) in [
+		("./samples/section_fat_zst.blk", None),
+		("./samples/section_slim_zst_dict.blk", Some(&frame_decoder)),
+		("./samples/section_slim_zst.blk", None),
+		("./samples/section_fat.blk", None),
+	] {
+		let file = fs::read(sample).unwrap();
+		let output = unpack_blk_from(
+			&file,
+			&mut scratch,
+			dictionary,
+			Some(nm.clone()),
+			ParseOptions::default(),
+		)
+		.unwrap();
+		assert_eq!(make_strict_test(), output, "{sample}");
+		assert_eq!(file, fs::read(sample).unwrap());
+	}
+
+	let unpack = |file: &[u8], scratch: &mut BlkScratch| {
+		unpack_blk_from(file, scratch, None, None, ParseOptions::default())
+	};
+	assert!(matches!(
+		unpack(&[], &mut scratch),
+		Err(WtBlkError::Parse(ParseError::EmptyFile))
+	));
+	assert!(matches!(
+		unpack(&[0xAA, 0, 0], &mut scratch),
+		Err(WtBlkError::Parse(ParseError::UnrecognizedBlkHeader {
+			header: 0xAA
+		}))
+	));
+	assert!(matches!(
+		unpack(&[FileType::FAT_ZSTD as u8, 0xFF], &mut scratch),
+		Err(WtBlkError::Parse(ParseError::BinaryDecoderError(_)))
+	));
+}
+
 #[test]
 fn fat_blk_router_probe() {
 	let file = fs::read("./samples/route_prober.blk").unwrap();
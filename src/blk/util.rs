@@ -1,9 +1,10 @@
-use std::{ffi::OsStr, sync::Arc};
+#[cfg(feature = "vromf")]
+use std::ffi::OsStr;
+use std::sync::Arc;
 
-use crate::{
-	blk::{blk_type::BlkString, file::FileType},
-	vromf::File,
-};
+use crate::blk::blk_type::BlkString;
+#[cfg(feature = "vromf")]
+use crate::{blk::file::FileType, vromf::File};
 
 #[inline(always)]
 pub(crate) fn bytes_to_offset(input: &[u8]) -> Option<usize> {
@@ -58,6 +59,7 @@ pub fn blk_str(s: &str) -> BlkString {
 }
 
 /// Simple check to differentiate plaintext BLK from binary one
+#[cfg(feature = "vromf")]
 pub fn maybe_blk(file: &File) -> bool {
 	file.path().extension() == Some(OsStr::new("blk"))
 		&& file.buf().len() > 0
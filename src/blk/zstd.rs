@@ -1,38 +1,127 @@
-use std::io::{BufReader, Read};
+use std::io;
 
-use color_eyre::{eyre::ContextCompat, Report};
-use zstd::{dict::DecoderDictionary, Decoder};
+use zstd::{
+	dict::DecoderDictionary,
+	zstd_safe::{
+		get_error_name,
+		get_frame_content_size,
+		DCtx,
+		InBuffer,
+		OutBuffer,
+		ResetDirective,
+	},
+};
 
-use crate::blk::file::FileType;
+use crate::{
+	binary_decoder::BinaryDecoder,
+	blk::{error::ParseError, file::FileType},
+	error::WtBlkError,
+};
+
+/// Upper bound of what is reserved up front, as frames may claim any decompressed size
+const MAX_PREALLOCATION: usize = 16 * 1024 * 1024;
 
 /// Decodes zstd compressed file using shared dictionary if available
 pub fn decode_zstd(
 	file_type: FileType,
 	file: &[u8],
 	frame_decoder: Option<&DecoderDictionary>,
-) -> Result<Vec<u8>, Report> {
-	let (len, to_decode) = if !file_type.is_slim() {
-		let len_raw = &file[1..4];
-		let len = u32::from_be_bytes([0, len_raw[2], len_raw[1], len_raw[0]]);
-		let to_decode = &file[4..(len as usize + 4)];
-		(len as usize, to_decode)
-	} else {
-		(file.len() - 1, &file[1..])
-	};
-	let mut out = Vec::with_capacity(len);
-
-	let mut decoder = if file_type.needs_dict() {
-		Decoder::with_prepared_dictionary(
-			BufReader::new(&file[1..]),
-			frame_decoder.context(format!(
-				"File type: {file_type} marked as having dictionary, but none was passed"
-			))?,
-		)?
-	} else {
-		Decoder::new(to_decode)?
-	};
-	let _ = decoder.read_to_end(&mut out)?;
-	Ok(out)
+) -> Result<Vec<u8>, WtBlkError> {
+	Ok(BlkScratch::new()
+		.decode_zstd(file_type, file, frame_decoder)?
+		.to_vec())
+}
+
+/// Zstd context and output buffer that are reused between files, such that unpacking many files does not allocate for each
+#[derive(Default)]
+pub struct BlkScratch<'d> {
+	context: DCtx<'d>,
+	buf:     Vec<u8>,
+}
+
+impl<'d> BlkScratch<'d> {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Decompresses the file into the scratch buffer, which is overwritten by the next call
+	pub fn decode_zstd(
+		&mut self,
+		file_type: FileType,
+		file: &[u8],
+		frame_decoder: Option<&DecoderDictionary<'d>>,
+	) -> Result<&[u8], WtBlkError> {
+		let frames = compressed_frames(file_type, file)?;
+		self.buf.clear();
+
+		self.context
+			.reset(ResetDirective::SessionAndParameters)
+			.map_err(zstd_error)?;
+		if file_type.needs_dict() {
+			let dict = frame_decoder.ok_or(ParseError::MissingDict {})?;
+			self.context
+				.ref_ddict(dict.as_ddict())
+				.map_err(zstd_error)?;
+		}
+		let res = self.decompress(frames);
+		// The dictionary is only borrowed for this call, so the context may not keep referring to it
+		self.context
+			.reset(ResetDirective::SessionAndParameters)
+			.map_err(zstd_error)?;
+		res?;
+		Ok(&self.buf)
+	}
+
+	/// Decompresses all frames, growing the buffer whenever it fills up
+	fn decompress(&mut self, frames: &[u8]) -> io::Result<()> {
+		if let Ok(Some(size)) = get_frame_content_size(frames) {
+			self.buf.reserve(
+				usize::try_from(size).map_or(MAX_PREALLOCATION, |size| size.min(MAX_PREALLOCATION)),
+			);
+		}
+		let mut input = InBuffer::around(frames);
+		loop {
+			if self.buf.len() == self.buf.capacity() {
+				self.buf.reserve(frames.len().max(4096));
+			}
+			let pos = self.buf.len();
+			let mut output = OutBuffer::around_pos(&mut self.buf, pos);
+			let hint = self
+				.context
+				.decompress_stream(&mut output, &mut input)
+				.map_err(zstd_error)?;
+			let output_full = output.pos() == self.buf.capacity();
+
+			if input.pos() == frames.len() {
+				// A hint of 0 means the frame is complete, otherwise more output or input is needed
+				if hint == 0 {
+					return Ok(());
+				}
+				if !output_full {
+					return Err(io::Error::new(
+						io::ErrorKind::UnexpectedEof,
+						"zstd frame ended prematurely",
+					));
+				}
+			}
+		}
+	}
+}
+
+/// Strips the file type byte, and the length prefix FAT files have
+fn compressed_frames(file_type: FileType, file: &[u8]) -> Result<&[u8], ParseError> {
+	let mut decoder = BinaryDecoder::new(file);
+	decoder.seek(1)?;
+	if file_type.is_slim() {
+		return Ok(decoder.remaining());
+	}
+	let [a, b, c] = decoder.next_array()?;
+	let len = u32::from_le_bytes([a, b, c, 0]) as usize;
+	Ok(decoder.next_slice(len)?)
+}
+
+fn zstd_error(code: usize) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, get_error_name(code))
 }
 
 #[cfg(test)]
@@ -41,7 +130,10 @@ mod test {
 
 	use zstd::{dict::DecoderDictionary, Decoder};
 
-	use crate::blk::{file::FileType, zstd::decode_zstd};
+	use crate::{
+		blk::{error::ParseError, file::FileType, zstd::decode_zstd},
+		error::WtBlkError,
+	};
 
 	#[test]
 	fn fat_zstd() {
@@ -68,6 +160,13 @@ mod test {
 		) // Truncating the first byte, as it is magic byte for the SLIM format
 	}
 
+	#[test]
+	fn missing_dict() {
+		let file = fs::read("./samples/section_slim_zst_dict.blk").unwrap();
+		let err = decode_zstd(FileType::SLIM_ZST_DICT, &file, None).unwrap_err();
+		assert!(matches!(err, WtBlkError::Parse(ParseError::MissingDict {})));
+	}
+
 	#[test]
 	fn slim_zstd_dict() {
 		let file = fs::read("./samples/section_slim_zst_dict.blk").unwrap();
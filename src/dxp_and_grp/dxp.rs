@@ -1,10 +1,8 @@
-use std::{ffi::CStr, mem::size_of};
-
 use crate::{
-	blk::util::bytes_to_offset,
+	binary_decoder::BinaryDecoder,
 	dxp_and_grp::{
 		dxp::DxpGrpError::{FileTooShort, InvalidHeader},
-		error::{DxpGrpError, DxpGrpError::IndexingFileOutOfBounds},
+		error::DxpGrpError,
 	},
 };
 
@@ -29,27 +27,15 @@ pub fn parse_dxp(file: &[u8]) -> Result<Vec<String>, DxpGrpError> {
 	}
 
 	// Fixed offset at 0x8
-	let file_count = bytes_to_offset(&file.get(0x8..(0x8 + size_of::<u32>())).ok_or(
-		IndexingFileOutOfBounds {
-			current_ptr: 0x8,
-			file_size:   file.len(),
-		},
-	)?)
-	.expect("Infallible");
+	let mut decoder = BinaryDecoder::new(file);
+	decoder.seek_to(0x8)?;
+	let file_count = decoder.next_u32_usize()?;
 
 	// Names begin at 0x48, usual CString sequence
-	let mut ptr: usize = 0x48;
+	decoder.seek_to(0x48)?;
 	let mut names = Vec::with_capacity(file_count);
 	for _ in 0..file_count {
-		let str = CStr::from_bytes_until_nul(&file.get(ptr..).ok_or(IndexingFileOutOfBounds {
-			current_ptr: ptr,
-			file_size:   file.len(),
-		})?)?
-		.to_str()?
-		.to_owned();
-		//              +1 for null
-		ptr += str.len() + 1;
-		names.push(str);
+		names.push(std::str::from_utf8(decoder.next_cstr()?)?.to_owned());
 	}
 	Ok(names)
 }
@@ -1,6 +1,7 @@
-use core::ffi::FromBytesUntilNulError;
 use std::str::Utf8Error;
 
+use crate::binary_decoder::BinaryDecoderError;
+
 #[derive(Debug, thiserror::Error)]
 pub enum DxpGrpError {
 	#[error(
@@ -8,17 +9,11 @@ pub enum DxpGrpError {
 	)]
 	InvalidHeader { found: String },
 
-	#[error(transparent)]
-	CStringError(#[from] FromBytesUntilNulError),
-
 	#[error(transparent)]
 	Utf8Error(#[from] Utf8Error),
 
-	#[error("current ptr {current_ptr} is out of bounds for buffer: {file_size} bytes")]
-	IndexingFileOutOfBounds {
-		current_ptr: usize,
-		file_size:   usize,
-	},
+	#[error(transparent)]
+	Decoder(#[from] BinaryDecoderError),
 
 	#[error("The file was a valid, but cut short before the names section, minimum bytes are 0x48, but the file was only {len:X}")]
 	FileTooShort { len: usize },
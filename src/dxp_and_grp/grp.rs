@@ -1,10 +1,8 @@
-use std::{ffi::CStr, mem::size_of};
-
 use crate::{
-	blk::util::bytes_to_offset,
+	binary_decoder::BinaryDecoder,
 	dxp_and_grp::error::{
 		DxpGrpError,
-		DxpGrpError::{FileTooShort, IndexingFileOutOfBounds, InvalidHeader},
+		DxpGrpError::{FileTooShort, InvalidHeader},
 	},
 };
 
@@ -18,28 +16,16 @@ pub fn parse_grp(file: &[u8]) -> Result<Vec<String>, DxpGrpError> {
 		return Err(InvalidHeader { found: grp_header });
 	}
 
-	// Fixed offset at 0x8
-	let file_count = bytes_to_offset(&file.get(0x14..(0x14 + size_of::<u32>())).ok_or(
-		IndexingFileOutOfBounds {
-			current_ptr: 0x24,
-			file_size:   file.len(),
-		},
-	)?)
-	.expect("Infallible");
+	// Fixed offset at 0x14
+	let mut decoder = BinaryDecoder::new(file);
+	decoder.seek_to(0x14)?;
+	let file_count = decoder.next_u32_usize()?;
 
 	// Names begin at 0x40, usual CString sequence
-	let mut ptr: usize = 0x40;
+	decoder.seek_to(0x40)?;
 	let mut names = Vec::with_capacity(file_count);
 	for _ in 0..file_count {
-		let str = CStr::from_bytes_until_nul(&file.get(ptr..).ok_or(IndexingFileOutOfBounds {
-			current_ptr: ptr,
-			file_size:   file.len(),
-		})?)?
-		.to_str()?
-		.to_owned();
-		//              +1 for null
-		ptr += str.len() + 1;
-		names.push(str);
+		names.push(std::str::from_utf8(decoder.next_cstr()?)?.to_owned());
 	}
 	Ok(names)
 }
@@ -0,0 +1,91 @@
+use std::{
+	fmt::{Display, Formatter},
+	io,
+	path::PathBuf,
+	string::FromUtf8Error,
+};
+
+use thiserror::Error;
+#[cfg(feature = "zip")]
+use zip::result::ZipError;
+
+#[cfg(feature = "dxp")]
+use crate::dxp_and_grp::error::DxpGrpError;
+#[cfg(feature = "vromf")]
+use crate::vromf::error::VromfError;
+use crate::{
+	binary_decoder::BinaryDecoderError,
+	binary_encoder::BinaryEncoderError,
+	blk::error::ParseError,
+};
+
+/// Error returned by the public API, wrapping the errors of the individual formats
+#[derive(Debug, Error)]
+pub enum WtBlkError {
+	#[error(transparent)]
+	Parse(#[from] ParseError),
+
+	#[cfg(feature = "dxp")]
+	#[error(transparent)]
+	DxpGrp(#[from] DxpGrpError),
+
+	#[cfg(feature = "vromf")]
+	#[error(transparent)]
+	Vromf(#[from] VromfError),
+
+	/// Reading a container outside of the BLK parsers failed, such as when it ended early
+	#[error(transparent)]
+	Decoder(#[from] BinaryDecoderError),
+
+	#[error(transparent)]
+	Encoder(#[from] BinaryEncoderError),
+
+	#[error("{digest} mismatch! Expected {expected} but found {found}")]
+	DigestMismatch {
+		digest:   Digest,
+		expected: String,
+		found:    String,
+	},
+
+	/// Context for errors that occurred while processing a file inside of a vromf
+	#[error("Failed to unpack {}", path.display())]
+	InFile {
+		path:   PathBuf,
+		#[source]
+		source: Box<WtBlkError>,
+	},
+
+	#[error("Pointer {pointer:?} could not be resolved: {reason}")]
+	InvalidPointer {
+		pointer: String,
+		reason:  &'static str,
+	},
+
+	#[error(transparent)]
+	Io(#[from] io::Error),
+
+	#[error(transparent)]
+	Utf8(#[from] FromUtf8Error),
+
+	#[cfg(feature = "zip")]
+	#[error(transparent)]
+	Zip(#[from] ZipError),
+}
+
+/// Hash function of a digest
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Digest {
+	/// Covers an entire vromf image
+	Md5,
+	/// Covers a single file inside of a vromf
+	Sha1,
+}
+
+impl Display for Digest {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		f.write_str(match self {
+			Digest::Md5 => "MD5",
+			Digest::Sha1 => "SHA1",
+		})
+	}
+}
@@ -6,20 +6,27 @@
 pub mod blk;
 
 /// Misc. utility functions for the DXP and GRP file-format
+#[cfg(feature = "dxp")]
 pub mod dxp_and_grp;
 
+/// Crate-wide error type returned by the public API
+pub mod error;
+
 /// General utility functions used in the entire crate
 mod util;
 
 /// High-level API for unpacking entire Vromf archives
+#[cfg(feature = "vromf")]
 pub mod vromf;
 
 /// Experimental WRPL unpacking (WIP)
 mod wrpl;
 
-/// Utility struct which wraps
-#[allow(unused)]
-mod binary_decoder;
+/// Bounds-checked reader shared by the binary parsers
+pub mod binary_decoder;
+
+/// Writer counterpart of [`binary_decoder`]
+pub mod binary_encoder;
 /// Performance instrumentation, using the stamp! macro
 #[allow(unused)]
 mod perf_instrumentation;
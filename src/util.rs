@@ -22,7 +22,7 @@ pub(crate) fn format_hex(hex: &[u8]) -> Vec<String> {
 #[allow(unused)]
 #[coverage(off)]
 pub(crate) fn join_hex(hex: &[u8]) -> String {
-	hex.iter().map(|x| format!("{x:x}")).join("")
+	hex.iter().map(|x| format!("{x:02x}")).join("")
 }
 
 #[allow(unused)]
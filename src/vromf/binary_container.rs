@@ -1,121 +1,185 @@
-use std::mem::size_of;
+use std::borrow::Cow;
 
-use color_eyre::{eyre::bail, Report, Section};
 use wt_version::Version;
 
-use crate::vromf::{
-	de_obfuscation::deobfuscate,
-	enums::{HeaderType, PlatformType},
-	header::Metadata,
-	util::{bytes_to_int, pack_type_from_aligned},
+use crate::{
+	binary_decoder::{BinaryDecoder, BinaryDecoderError},
+	binary_encoder::BinaryEncoder,
+	error::{Digest, WtBlkError},
+	util::join_hex,
+	vromf::{
+		de_obfuscation::{deobfuscate, obfuscate},
+		enums::{HeaderType, PlatformType},
+		error::VromfError,
+		header::Metadata,
+		util::{pack_type_from_aligned, pack_type_to_aligned},
+	},
 };
 
-pub(crate) fn decode_bin_vromf(file: &[u8], validate: bool) -> Result<(Vec<u8>, Metadata), Report> {
-	let mut metadata = Metadata::default();
+/// Size of the extended header, following the regular header
+const EXTENDED_HEADER_SIZE: u16 = 8;
+
+/// Unpacks a vromf image into its inner container and metadata, checking the MD5 when `validate` is set
+pub fn decode_bin_vromf(
+	file: &[u8],
+	validate: bool,
+) -> Result<(Vec<u8>, Metadata), WtBlkError> {
+	let decoded = decode_bin_vromf_unchecked(file)?;
+	if let (true, Some(stored_md5)) = (validate, decoded.stored_md5) {
+		let expected = stored_md5?;
+		let computed_hash = md5::compute(&decoded.output).0;
+		if expected != computed_hash {
+			return Err(WtBlkError::DigestMismatch {
+				digest:   Digest::Md5,
+				expected: join_hex(&expected),
+				found:    join_hex(&computed_hash),
+			});
+		}
+	}
+	Ok((decoded.output, decoded.metadata))
+}
+
+/// Decoded vromf, along with the MD5 of `output` stored after the packed data
+pub(crate) struct DecodedBinVromf {
+	pub output:     Vec<u8>,
+	pub metadata:   Metadata,
+	/// Absent when the packing carries no hash, and an error when the file ended before it
+	pub stored_md5: Option<Result<[u8; 16], BinaryDecoderError>>,
+}
 
-	let mut ptr = 0_usize;
+pub(crate) fn decode_bin_vromf_unchecked(file: &[u8]) -> Result<DecodedBinVromf, WtBlkError> {
+	let mut metadata = Metadata::default();
 
-	// Returns slice offset from file, incrementing the ptr by offset
-	let idx_file_offset = |ptr: &mut usize, offset: usize| {
-		if let Some(buff) = file.get(*ptr..(*ptr + offset)) {
-			*ptr += offset;
-			Ok(buff)
-		} else {
-			Err(Report::msg(format!(
-				"Indexing buffer of size {} with index {} and length {}",
-				file.len(),
-				*ptr,
-				offset
-			)))
-		}
-	};
+	let mut decoder = BinaryDecoder::new(file);
 
-	let header_type = bytes_to_int(idx_file_offset(&mut ptr, 4)?)?;
-	let header_type = HeaderType::try_from(header_type)?;
+	let header_type = HeaderType::try_from(decoder.next_u32()?)?;
 	metadata.header_type = Some(header_type);
 
-	let platform_raw = bytes_to_int(idx_file_offset(&mut ptr, 4)?)?;
-	let platform = PlatformType::try_from(platform_raw)?;
+	let platform = PlatformType::try_from(decoder.next_u32()?)?;
 	metadata.platform = Some(platform);
 
 	// Size of the file before compression
-	let size = bytes_to_int(idx_file_offset(&mut ptr, 4)?)?;
+	let size = decoder.next_u32_usize()?;
 
-	let header_packed: u32 = bytes_to_int(idx_file_offset(&mut ptr, 4)?)?;
+	let header_packed = decoder.next_u32()?;
 
 	// Type of compression/packing, and size before compression
 	let (pack_type, extended_header_size) = pack_type_from_aligned(header_packed)?;
 	metadata.packing = Some(pack_type);
 
 	let inner_data = if header_type.is_extended() {
-		let extended_header = idx_file_offset(
-			&mut ptr,
-			size_of::<u16>() + size_of::<u16>() + size_of::<u32>(),
-		)?;
-		let s = extended_header; // Copying ptr such that indexing below is less verbose
-
 		// Unused header elements, for now
-		let _header_size = u16::from_le_bytes([s[0], s[1]]);
-		let _flags = u16::from_le_bytes([s[2], s[3]]);
+		let _header_size = decoder.next_u16()?;
+		let _flags = decoder.next_u16()?;
 		// The version is always reversed in order. It may never exceed 255
-		let version = [s[7], s[6], s[5], s[4]];
-		metadata.version = Some(Version::new(
-			version[0] as u16,
-			version[1] as u16,
-			version[2] as u16,
-			version[3] as u16,
-		));
+		let [d, c, b, a] = decoder.next_array::<4>()?;
+		metadata.version = Some(Version::new(a as u16, b as u16, c as u16, d as u16));
 
 		// Null length means the remaining bytes are used
 		if extended_header_size == 0 {
-			&file[ptr..]
+			decoder.next_slice(decoder.remaining().len())?
 		} else {
-			idx_file_offset(&mut ptr, extended_header_size as usize)?
+			decoder.next_slice(extended_header_size as usize)?
 		}
 	} else {
 		if pack_type.is_compressed() {
-			idx_file_offset(&mut ptr, extended_header_size as usize)?
+			decoder.next_slice(extended_header_size as usize)?
 		} else {
-			idx_file_offset(&mut ptr, size as usize)?
+			decoder.next_slice(size)?
 		}
 	};
 
-	// Directly return when data is not obfuscated
-	if !pack_type.is_obfuscated() {
-		return Ok((inner_data.to_vec(), metadata));
-	}
-
 	let mut output = inner_data.to_vec();
-	deobfuscate(&mut output);
+	if pack_type.is_obfuscated() {
+		deobfuscate(&mut output);
+	}
 
 	if pack_type.is_compressed() {
-		output = zstd::decode_all(output.as_slice())
-			.note("This most likely occurred because of improper computation of the frame-size")?;
+		output = zstd::decode_all(output.as_slice()).map_err(VromfError::Decompression)?;
 	}
 
-	if pack_type.has_hash() && validate {
-		let expected = idx_file_offset(&mut ptr, 16)?;
-		let computed_hash = md5::compute(&output);
-		if expected != &computed_hash.0 {
-			bail!(
-				"Hash missmatch! Expected {:x} but found {computed_hash:x}",
-				u128::from_le_bytes(expected.try_into()?)
+	let stored_md5 = pack_type.has_hash().then(|| decoder.next_array::<16>());
+	Ok(DecodedBinVromf {
+		output,
+		metadata,
+		stored_md5,
+	})
+}
+
+/// Packs an inner container into a vromf image, the inverse of [`decode_bin_vromf`].
+/// Header type, platform and packing are required, the version only for extended headers
+pub fn encode_bin_vromf(input: &[u8], meta: Metadata) -> Result<Vec<u8>, WtBlkError> {
+	let header_type = meta.header_type.ok_or(VromfError::IncompleteMetadata {
+		field: "header type",
+	})?;
+	let platform = meta
+		.platform
+		.ok_or(VromfError::IncompleteMetadata { field: "platform" })?;
+	let packing = meta
+		.packing
+		.ok_or(VromfError::IncompleteMetadata { field: "packing" })?;
+
+	let inner_data = if packing.is_compressed() {
+		let mut compressed = zstd::encode_all(input, zstd::DEFAULT_COMPRESSION_LEVEL)
+			.map_err(VromfError::Compression)?;
+		obfuscate(&mut compressed);
+		Cow::Owned(compressed)
+	} else {
+		Cow::Borrowed(input)
+	};
+
+	// Plain simple images leave the packed size empty, as they are read up to the size before compression.
+	// Extended images read the remainder of the file instead, which would include the digest
+	let packed_size = if packing.is_compressed() || header_type.is_extended() {
+		inner_data.len()
+	} else {
+		0
+	};
+	let size = u32::try_from(input.len()).map_err(|_| VromfError::SizeOverflow {
+		size: input.len(),
+		max:  u32::MAX as usize,
+	})?;
+
+	let mut encoder = BinaryEncoder::with_capacity(inner_data.len() + 48);
+	encoder.write_u32(header_type as u32);
+	encoder.write_u32(platform as u32);
+	encoder.write_u32(size);
+	encoder.write_u32(pack_type_to_aligned(packing, packed_size)?);
+
+	if header_type.is_extended() {
+		let version = meta
+			.version
+			.ok_or(VromfError::IncompleteMetadata { field: "version" })?;
+		encoder.write_u16(EXTENDED_HEADER_SIZE);
+		let flags = 0;
+		encoder.write_u16(flags);
+		// Components are stored as single bytes, in reverse order
+		for shift in [0, 16, 32, 48] {
+			let component = (version.to_u64() >> shift) as u16;
+			encoder.write_u8(
+				u8::try_from(component).map_err(|_| VromfError::UnencodableVersion(version))?,
 			);
 		}
 	}
 
-	Ok((output, metadata))
-}
-
-pub(crate) fn _encode_bin_vromf(_input: &[u8], _meta: Metadata) -> Result<Vec<u8>, Report> {
-	todo!()
+	encoder.write_bytes(&inner_data);
+	if packing.has_hash() {
+		encoder.write_bytes(&md5::compute(input).0);
+	}
+	Ok(encoder.into_inner())
 }
 
 #[cfg(test)]
 mod test {
 	use std::fs;
 
-	use crate::vromf::binary_container::decode_bin_vromf;
+	use wt_version::Version;
+
+	use crate::vromf::{
+		binary_container::{decode_bin_vromf, encode_bin_vromf},
+		enums::{HeaderType, Packing, PlatformType},
+		header::Metadata,
+	};
 
 	#[test]
 	fn decode_compressed() {
@@ -123,13 +187,52 @@ mod test {
 		decode_bin_vromf(&f, true).unwrap();
 	}
 
-	// #[test]
-	// fn two_way() {
-	// 	let f = fs::read("./samples/unchecked_extended_compressed_checked.vromfs.bin").unwrap();
-	// 	let (decoded, meta) = decode_bin_vromf(&f).unwrap();
-	// 	let re_encoded = encode_bin_vromf(&decoded, meta).unwrap();
-	// 	assert_eq!(re_encoded, f);
-	// }
+	#[test]
+	fn two_way() {
+		let f = fs::read("./samples/checked_simple_uncompressed_checked.vromfs.bin").unwrap();
+		let (decoded, meta) = decode_bin_vromf(&f, true).unwrap();
+		let re_encoded = encode_bin_vromf(&decoded, meta).unwrap();
+		assert_eq!(re_encoded, f);
+	}
+
+	#[test]
+	fn all_packings() {
+		let f = fs::read("./samples/unchecked_extended_compressed_checked.vromfs.bin").unwrap();
+		let (decoded, _) = decode_bin_vromf(&f, true).unwrap();
+
+		for header_type in [HeaderType::VRFS, HeaderType::VRFX] {
+			for packing in [
+				Packing::ZSTD_OBFS_NOCHECK,
+				Packing::PLAIN,
+				Packing::ZSTD_OBFS,
+			] {
+				let meta = Metadata {
+					header_type: Some(header_type),
+					platform:    Some(PlatformType::Android),
+					packing:     Some(packing),
+					version:     header_type
+						.is_extended()
+						.then_some(Version::new(2, 41, 0, 17)),
+				};
+				let encoded = encode_bin_vromf(&decoded, meta.clone()).unwrap();
+				let (re_decoded, re_meta) = decode_bin_vromf(&encoded, true).unwrap();
+				assert_eq!(re_decoded, decoded, "{header_type:?} {packing:?}");
+				assert_eq!(re_meta, meta);
+			}
+		}
+	}
+
+	#[test]
+	fn unencodable_metadata() {
+		assert!(encode_bin_vromf(&[], Metadata::default()).is_err());
+		let meta = Metadata {
+			header_type: Some(HeaderType::VRFX),
+			platform:    Some(PlatformType::Pc),
+			packing:     Some(Packing::PLAIN),
+			version:     Some(Version::new(2, 300, 0, 0)),
+		};
+		assert!(encode_bin_vromf(&[], meta).is_err());
+	}
 
 	// #[test]
 	// fn test_regional() {
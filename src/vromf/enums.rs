@@ -1,6 +1,6 @@
 use std::fmt::{Display, Formatter};
 
-use color_eyre::{eyre::bail, Report};
+use crate::vromf::error::VromfError;
 
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
 #[allow(non_camel_case_types)]
@@ -36,13 +36,13 @@ impl Display for HeaderType {
 }
 
 impl TryFrom<u32> for HeaderType {
-	type Error = Report;
+	type Error = VromfError;
 
 	fn try_from(value: u32) -> Result<Self, Self::Error> {
 		return match value {
 			0x73465256 => Ok(Self::VRFS),
 			0x78465256 => Ok(Self::VRFX),
-			_ => bail!("Unknown header type: {value:X}"),
+			_ => Err(VromfError::UnknownHeaderType(value)),
 		};
 	}
 }
@@ -80,14 +80,14 @@ impl Display for PlatformType {
 }
 
 impl TryFrom<u32> for PlatformType {
-	type Error = Report;
+	type Error = VromfError;
 
 	fn try_from(value: u32) -> Result<Self, Self::Error> {
 		return match value {
 			0x43500000 => Ok(Self::Pc),
 			0x534F6900 => Ok(Self::Ios),
 			0x646E6100 => Ok(Self::Android),
-			_ => bail!("Unknown platform {value:X}"),
+			_ => Err(VromfError::UnknownPlatform(value)),
 		};
 	}
 }
@@ -132,14 +132,14 @@ impl Packing {
 }
 
 impl TryFrom<u8> for Packing {
-	type Error = Report;
+	type Error = VromfError;
 
 	fn try_from(value: u8) -> Result<Self, Self::Error> {
 		return match value {
 			0x10 => Ok(Self::ZSTD_OBFS_NOCHECK),
 			0x20 => Ok(Self::PLAIN),
 			0x30 => Ok(Self::ZSTD_OBFS),
-			_ => bail!("Unknown packing type: {value:X}"),
+			_ => Err(VromfError::UnknownPacking(value)),
 		};
 	}
 }
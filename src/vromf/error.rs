@@ -0,0 +1,60 @@
+use std::{io, path::PathBuf, string::FromUtf8Error};
+
+use wt_version::Version;
+
+#[derive(Debug, thiserror::Error)]
+pub enum VromfError {
+	#[error("Unknown header type: {0:X}")]
+	UnknownHeaderType(u32),
+
+	#[error("Unknown platform {0:X}")]
+	UnknownPlatform(u32),
+
+	#[error("Unknown packing type: {0:X}")]
+	UnknownPacking(u8),
+
+	#[error("Unknown digest header {0:X}")]
+	UnknownDigestHeader(u8),
+
+	#[error("Zstd decompression failed, most likely because of an improper computation of the frame-size")]
+	Decompression(#[source] io::Error),
+
+	#[error("Zstd compression failed")]
+	Compression(#[source] io::Error),
+
+	#[error("Metadata is missing its {field}, which is required for encoding")]
+	IncompleteMetadata { field: &'static str },
+
+	#[error("Size {size} exceeds the maximum of {max} the header can store")]
+	SizeOverflow { size: usize, max: usize },
+
+	#[error("Version {0} has components above 255, which the extended header cannot store")]
+	UnencodableVersion(Version),
+
+	#[error("Digest does not align to multiple of 20 bytes")]
+	UnalignedDigest,
+
+	#[error("Too few digest elements")]
+	MissingDigest,
+
+	#[error("Invalid UTF-8 sequence in file name")]
+	InvalidFileName(#[from] FromUtf8Error),
+
+	#[error("File name {} is not valid UTF-8", .0.display())]
+	UnencodableFileName(PathBuf),
+
+	#[error("File {} would be extracted outside of the target directory", .0.display())]
+	UnsafePath(PathBuf),
+
+	#[error("File {} has a path too long for a tar archive", .0.display())]
+	UnarchivablePath(PathBuf),
+
+	#[error("File {} of {size} bytes is too large for a tar archive", .path.display())]
+	UnarchivableSize { path: PathBuf, size: usize },
+
+	#[error("File {} was not found in VROMF", .0.display())]
+	FileNotFound(PathBuf),
+
+	#[error("Invalid version file contents: {0}")]
+	InvalidVersion(String),
+}
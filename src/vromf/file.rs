@@ -1,5 +1,6 @@
 use std::{
 	fs,
+	io,
 	path::{Path, PathBuf},
 };
 
@@ -10,7 +11,7 @@ pub struct File {
 }
 
 impl File {
-	pub fn new(p: impl Into<PathBuf>) -> color_eyre::Result<Self> {
+	pub fn new(p: impl Into<PathBuf>) -> io::Result<Self> {
 		let path = p.into();
 		Ok(Self {
 			file: fs::read(&path)?,
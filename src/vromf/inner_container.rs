@@ -1,62 +1,70 @@
-use std::{mem::size_of, path::PathBuf};
-
-use color_eyre::{
-	eyre::{bail, Context, ContextCompat},
-	Report,
+use std::{
+	mem::size_of,
+	ops::Range,
+	path::{Path, PathBuf},
+	slice::ChunksExact,
 };
-use fallible_iterator::{convert, FallibleIterator};
+
 use sha1_smol::Sha1;
 
 use crate::{
+	binary_decoder::{BinaryDecoder, BinaryDecoderError},
+	binary_encoder::BinaryEncoder,
+	error::{Digest, WtBlkError},
 	util::join_hex,
-	vromf::{
-		util::{bytes_to_int, bytes_to_usize},
-		File,
-	},
+	vromf::{error::VromfError, File},
 };
 
-pub fn decode_inner_vromf(file: &[u8], validate: bool) -> Result<Vec<File>, Report> {
-	// Returns slice offset from file, incrementing the ptr by offset
-	let idx_file_offset = |ptr: &mut usize, offset: usize| {
-		if let Some(res) = file.get(*ptr..(*ptr + offset)) {
-			*ptr += offset;
-			Ok(res)
-		} else {
-			Err(Report::msg(format!(
-				"Indexing buffer of size {} with index {} and length {}",
-				file.len(),
-				*ptr,
-				offset
-			)))
-		}
-	};
-	let mut ptr = 0;
+/// Name the nm file is stored with
+const NM_BYTE_ID: &[u8] = b"\xff\x3fnm";
+
+pub fn decode_inner_vromf(file: &[u8], validate: bool) -> Result<Vec<File>, WtBlkError> {
+	Ok(index_inner_vromf(file, validate)?
+		.into_iter()
+		.map(|(path, range, _)| File::from_raw(path, file[range].to_vec()))
+		.collect())
+}
+
+/// Path, range within the container and SHA1 digest of a file, if the container has a digest
+pub(crate) type IndexedFile = (PathBuf, Range<usize>, Option<[u8; 20]>);
+
+/// Tables of the inner container, read without accessing the files they describe
+pub(crate) struct InnerTables<'a> {
+	pub names:     Vec<PathBuf>,
+	/// Offset and size of each file
+	pub data_info: Vec<(usize, usize)>,
+	/// SHA1 of each file, if the container has a digest
+	pub digests:   Option<ChunksExact<'a, u8>>,
+}
+
+pub(crate) fn read_inner_tables(
+	file: &[u8],
+	validate: bool,
+) -> Result<InnerTables<'_>, WtBlkError> {
+	let mut decoder = BinaryDecoder::new(file);
 
 	// The header indicates existence of a digest
-	let names_header = idx_file_offset(&mut ptr, size_of::<u32>())?;
-	let has_digest = match names_header[0] {
+	let names_offset = decoder.next_u32_usize()?;
+	let has_digest = match names_offset as u8 {
 		0x20 => false,
 		0x30 => true,
-		_ => {
-			bail!("Unknown digest header {:X}", names_header[0])
-		},
+		header => return Err(VromfError::UnknownDigestHeader(header).into()),
 	};
 
-	let names_offset = bytes_to_int(names_header)? as usize;
-	let names_count = bytes_to_int(idx_file_offset(&mut ptr, size_of::<u32>())?)? as usize;
-	ptr += size_of::<u32>() * 2; // Padding to 16 byte alignment
+	let names_count = decoder.next_u32_usize()?;
+	decoder.align_to(16)?;
 
-	let data_info_offset = bytes_to_int(idx_file_offset(&mut ptr, size_of::<u32>())?)? as usize;
-	let data_info_count = bytes_to_int(idx_file_offset(&mut ptr, size_of::<u32>())?)? as usize;
-	ptr += size_of::<u32>() * 2; // Padding to 16 byte alignment
+	let data_info_offset = decoder.next_u32_usize()?;
+	let data_info_count = decoder.next_u32_usize()?;
+	decoder.align_to(16)?;
 
-	let mut digest_data = if has_digest {
-		let digest_end = bytes_to_usize(idx_file_offset(&mut ptr, size_of::<u64>())?)?;
-		let digest_begin = bytes_to_usize(idx_file_offset(&mut ptr, size_of::<u64>())?)?;
-		let digest_data = &file[digest_begin..digest_end];
+	let digests = if has_digest {
+		let digest_end = decoder.next_u64_usize()?;
+		let digest_begin = decoder.next_u64_usize()?;
+		let digest_data = decoder.slice_at(digest_begin..digest_end)?;
 		let chunks = digest_data.chunks_exact(20);
 		if validate && chunks.remainder().len() != 0 {
-			bail!("Digest does not align to multiple of 20 bytes");
+			return Err(VromfError::UnalignedDigest.into());
 		}
 		Some(chunks)
 	} else {
@@ -64,86 +72,180 @@ pub fn decode_inner_vromf(file: &[u8], validate: bool) -> Result<Vec<File>, Repo
 	};
 
 	// Names info is a set of u64s, pointing at each name
-	let names_info_len = names_count * size_of::<u64>();
-	let names_info = &file[names_offset..(names_offset + names_info_len)];
-	let names_info_chunks = names_info.array_chunks::<{ size_of::<u64>() }>(); // No remainder from chunks as it is infallible
-	let parsed_names_offsets: Vec<usize> = names_info_chunks
+	decoder.seek_to(names_offset)?;
+	let parsed_names_offsets = (0..names_count)
+		.map(|_| decoder.next_u64_usize())
+		.collect::<Result<Vec<_>, _>>()?;
+	let names = parsed_names_offsets
 		.into_iter()
-		.map(|x| bytes_to_usize(x))
-		.collect::<Result<_, Report>>()?;
-	let file_names = parsed_names_offsets.into_iter().map(|start| {
-		let mut buff = Vec::with_capacity(32);
-		for byte in &file[start..] {
-			if *byte == 0 {
-				break;
-			} else {
-				buff.push(*byte)
-			}
-		}
-		// The nm file has a special case, where it has additional "garbage" bytes leading in-front of it
-		const NM_BYTE_ID: &[u8] = b"\xff\x3fnm";
-		if let Some(leading_bytes) = buff.get(..4) {
-			if leading_bytes == NM_BYTE_ID {
-				buff = b"nm".to_vec();
+		.map(|start| {
+			let mut name = BinaryDecoder::new(file);
+			name.seek_to(start)?;
+			let mut buff = name.next_cstr()?;
+			// The nm file has a special case, where it has additional "garbage" bytes leading in-front of it
+			if buff.starts_with(NM_BYTE_ID) {
+				buff = b"nm";
 			}
-		}
-		let s = String::from_utf8(buff)
-			.map(|res| PathBuf::from(res))
-			.context("Invalid UTF-8 sequence".to_string())?;
-		Ok::<PathBuf, Report>(s)
-	});
+			let s = String::from_utf8(buff.to_vec())
+				.map(PathBuf::from)
+				.map_err(VromfError::InvalidFileName)?;
+			Ok::<PathBuf, WtBlkError>(s)
+		})
+		.collect::<Result<Vec<_>, _>>()?;
 
 	// FYI:
 	// Each data-info-block consists of 4x u32
 	// Only the first two values are used, as offset and length, the remaining two values are 0
-	let data_info_len = data_info_count * size_of::<u32>() * 4; // Total length of the data-info block
-	let data_info = &file[data_info_offset..(data_info_offset + data_info_len)];
-	let data_info_split = data_info.array_chunks::<{ size_of::<u32>() }>(); // Data-info consists of u32 pairs, so we will split them once
-	if data_info_split.remainder().len() != 0 {
-		bail!("Unaligned chunks: the data-set of size {} was supposed to align/chunk into {}, but {} remained", data_info.len(), size_of::<u32>(), data_info_split.remainder().len());
-	}
-
-	// This has to align to 4, because of previous chunk checks
-	let data_info_full = data_info_split.array_chunks::<4>(); // Join together pairs of offset and length with 2 trailing bytes
-	let data = data_info_full
-		.map(|x| {
-			(
-				u32::from_le_bytes(*x[0]) as usize,
-				u32::from_le_bytes(*x[1]) as usize,
-			)
+	decoder.seek_to(data_info_offset)?;
+	let data_info = (0..data_info_count)
+		.map(|_| {
+			let offset = decoder.next_u32_usize()?;
+			let size = decoder.next_u32_usize()?;
+			decoder.seek(size_of::<u32>() * 2)?;
+			Ok((offset, size))
 		})
-		.map(|(offset, size)| file[offset..(offset + size)].to_vec())
-		.map(|e| {
+		.collect::<Result<Vec<_>, BinaryDecoderError>>()?;
+
+	Ok(InnerTables {
+		names,
+		data_info,
+		digests,
+	})
+}
+
+/// Locates each file within the container without copying it
+pub(crate) fn index_inner_vromf(
+	file: &[u8],
+	validate: bool,
+) -> Result<Vec<IndexedFile>, WtBlkError> {
+	let InnerTables {
+		names,
+		data_info,
+		mut digests,
+	} = read_inner_tables(file, validate)?;
+	let has_digest = digests.is_some();
+
+	names
+		.into_iter()
+		.zip(data_info)
+		.map(|(path, (offset, size))| {
+			let range = offset..offset.saturating_add(size);
+			let e = BinaryDecoder::new(file).slice_at(range.clone())?;
+			let digest = digests.as_mut().and_then(|e| e.next());
 			// Check digest only if the file should have one
 			if validate && has_digest {
-				let digest = digest_data
-					.as_mut()
-					.map(|e| e.next())
-					.context("Digest missing")?
-					.context("Too few digest elements")?;
-				let h = Sha1::from(&e).digest().bytes();
-				if digest != &h {
-					bail!(
-						"Hash missmatch: expected: {} but found {}",
-						join_hex(&e),
-						join_hex(&h)
-					);
+				let digest = digest.ok_or(VromfError::MissingDigest)?;
+				let h = Sha1::from(e).digest().bytes();
+				if digest != h {
+					return Err(WtBlkError::DigestMismatch {
+						digest:   Digest::Sha1,
+						expected: join_hex(digest),
+						found:    join_hex(&h),
+					});
 				}
 			}
-			Ok(e)
-		});
+			Ok((
+				path,
+				range,
+				digest.map(|digest| digest.try_into().expect("Infallible")),
+			))
+		})
+		.collect()
+}
+
+/// Packs files into an inner container, the inverse of [`decode_inner_vromf`].
+/// The digest stores the SHA1 of every file, such that they can be validated when decoding
+pub fn encode_inner_vromf(files: &[File], with_digest: bool) -> Result<Vec<u8>, WtBlkError> {
+	let names = files
+		.iter()
+		.map(|file| {
+			if file.path() == Path::new("nm") {
+				return Ok(NM_BYTE_ID);
+			}
+			file.path()
+				.to_str()
+				.map(str::as_bytes)
+				.ok_or_else(|| VromfError::UnencodableFileName(file.path().to_owned()))
+		})
+		.collect::<Result<Vec<_>, _>>()?;
+
+	// The header doubles as offset of the names info, and indicates the existence of a digest
+	let names_info_offset = if with_digest { 0x30 } else { 0x20 };
+	let names_offset = names_info_offset + files.len() * size_of::<u64>();
+	let names_len: usize = names.iter().map(|name| name.len() + 1).sum();
+	let data_info_offset = (names_offset + names_len).next_multiple_of(16);
+	let digest_begin = data_info_offset + files.len() * size_of::<u32>() * 4;
+	let digest_end = digest_begin + if with_digest { files.len() * 20 } else { 0 };
+
+	let mut encoder = BinaryEncoder::with_capacity(
+		digest_end
+			+ files
+				.iter()
+				.map(|file| file.buf().len() + 16)
+				.sum::<usize>(),
+	);
+	encoder.write_u32(offset_u32(names_info_offset)?);
+	encoder.write_u32(offset_u32(files.len())?);
+	encoder.align_to(16)?;
+	encoder.write_u32(offset_u32(data_info_offset)?);
+	encoder.write_u32(offset_u32(files.len())?);
+	encoder.align_to(16)?;
+	if with_digest {
+		encoder.write_u64(digest_end as u64);
+		encoder.write_u64(digest_begin as u64);
+	}
+
+	// Names info is a set of u64s, pointing at each name
+	let mut name_offset = names_offset;
+	for name in &names {
+		encoder.write_u64(name_offset as u64);
+		name_offset += name.len() + 1;
+	}
+	for name in &names {
+		encoder.write_cstr(name);
+	}
+	encoder.align_to(16)?;
+
+	// Each data-info-block is an offset and a length, padded to 16 bytes. Files are aligned to 16 bytes as well
+	let mut data_offset = digest_end.next_multiple_of(16);
+	for file in files {
+		encoder.write_u32(offset_u32(data_offset)?);
+		encoder.write_u32(offset_u32(file.buf().len())?);
+		encoder.write_u64(0);
+		data_offset = (data_offset + file.buf().len()).next_multiple_of(16);
+	}
+
+	if with_digest {
+		for file in files {
+			encoder.write_bytes(&Sha1::from(file.buf()).digest().bytes());
+		}
+	}
+	encoder.align_to(16)?;
 
-	Ok(convert(file_names)
-		.zip(convert(data))
-		.map(|(p, f)| Ok(File::from_raw(p, f)))
-		.collect()?)
+	for file in files {
+		encoder.write_bytes(file.buf());
+		encoder.align_to(16)?;
+	}
+	Ok(encoder.into_inner())
+}
+
+fn offset_u32(value: usize) -> Result<u32, VromfError> {
+	u32::try_from(value).map_err(|_| VromfError::SizeOverflow {
+		size: value,
+		max:  u32::MAX as usize,
+	})
 }
 
 #[cfg(test)]
 mod test {
 	use std::fs;
 
-	use crate::vromf::{binary_container::decode_bin_vromf, inner_container::decode_inner_vromf};
+	use crate::vromf::{
+		binary_container::{decode_bin_vromf, encode_bin_vromf},
+		enums::{HeaderType, Packing, PlatformType},
+		header::Metadata,
+		inner_container::{decode_inner_vromf, encode_inner_vromf},
+	};
 
 	#[test]
 	fn test_uncompressed() {
@@ -171,4 +273,36 @@ mod test {
 		let (decoded, _) = decode_bin_vromf(&f, true).unwrap();
 		let _inner = decode_inner_vromf(&decoded, true).unwrap();
 	}
+
+	#[test]
+	fn two_way() {
+		let f = fs::read("./samples/checked.vromfs").unwrap();
+		let files = decode_inner_vromf(&f, true).unwrap();
+		assert!(files.iter().any(|file| file.path().to_str() == Some("nm")));
+		assert_eq!(encode_inner_vromf(&files, true).unwrap(), f);
+	}
+
+	#[test]
+	fn mod_vromf() {
+		let f = fs::read("./samples/checked.vromfs").unwrap();
+		let files = decode_inner_vromf(&f, true).unwrap();
+
+		let inner = encode_inner_vromf(&files, false).unwrap();
+		let meta = Metadata {
+			header_type: Some(HeaderType::VRFS),
+			platform:    Some(PlatformType::Pc),
+			packing:     Some(Packing::ZSTD_OBFS),
+			version:     None,
+		};
+		let (decoded, _) =
+			decode_bin_vromf(&encode_bin_vromf(&inner, meta).unwrap(), true).unwrap();
+		let re_decoded = decode_inner_vromf(&decoded, true).unwrap();
+		assert_eq!(
+			files.iter().map(|file| file.as_ref()).collect::<Vec<_>>(),
+			re_decoded
+				.iter()
+				.map(|file| file.as_ref())
+				.collect::<Vec<_>>()
+		);
+	}
 }
@@ -0,0 +1,243 @@
+use std::path::PathBuf;
+
+use sha1_smol::Sha1;
+
+use crate::{
+	error::WtBlkError,
+	vromf::{
+		binary_container::decode_bin_vromf_unchecked,
+		inner_container::{read_inner_tables, InnerTables},
+	},
+};
+
+/// Outcome of checking the MD5 and all SHA1 digests of a vromf
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct VerifyReport {
+	/// MD5 of the entire image, absent when its packing carries no hash or [`VerifyReport::outer_error`] kept it from being checked
+	pub md5:          Option<DigestCheck>,
+	/// Whether the inner container stores a SHA1 for each file
+	pub file_digests: bool,
+	/// Amount of files in the inner container
+	pub files:        usize,
+	/// Every file that failed to verify
+	pub corrupted:    Vec<CorruptedFile>,
+	/// Why the image could not be unpacked into its inner container, such as truncation or corrupt ZSTD data.
+	/// No files are checked in that case
+	pub outer_error:  Option<String>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DigestCheck {
+	Valid,
+	/// The digest should exist, but the data ended before it
+	Missing,
+	Mismatch {
+		expected: Vec<u8>,
+		found:    Vec<u8>,
+	},
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CorruptedFile {
+	pub path:    PathBuf,
+	pub problem: FileProblem,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum FileProblem {
+	/// The file reaches past the end of the inner container
+	OutOfBounds { offset: usize, size: usize },
+	/// SHA1 of the file is missing or does not match, never [`DigestCheck::Valid`]
+	Digest(DigestCheck),
+}
+
+impl VerifyReport {
+	pub fn is_valid(&self) -> bool {
+		self.md5.as_ref().is_none_or(DigestCheck::is_valid)
+			&& self.corrupted.is_empty()
+			&& self.outer_error.is_none()
+	}
+}
+
+impl DigestCheck {
+	fn compare(expected: Option<&[u8]>, found: &[u8]) -> Self {
+		match expected {
+			None => Self::Missing,
+			Some(expected) if expected == found => Self::Valid,
+			Some(expected) => Self::Mismatch {
+				expected: expected.to_vec(),
+				found:    found.to_vec(),
+			},
+		}
+	}
+
+	pub fn is_valid(&self) -> bool {
+		*self == Self::Valid
+	}
+}
+
+/// Checks the MD5 of the image and the SHA1 of every file, collecting all mismatches instead of stopping at the first.
+/// Errors are only returned when the inner container is too damaged to locate its files
+pub fn verify(file: &[u8]) -> Result<VerifyReport, WtBlkError> {
+	let decoded = match decode_bin_vromf_unchecked(file) {
+		Ok(decoded) => decoded,
+		Err(e) => {
+			return Ok(VerifyReport {
+				outer_error: Some(e.to_string()),
+				..Default::default()
+			});
+		},
+	};
+	let md5 = decoded.stored_md5.map(|stored| {
+		DigestCheck::compare(
+			stored.ok().as_ref().map(|stored| stored.as_slice()),
+			&md5::compute(&decoded.output).0,
+		)
+	});
+
+	let inner = decoded.output.as_slice();
+	let InnerTables {
+		names,
+		data_info,
+		mut digests,
+	} = read_inner_tables(inner, false)?;
+	let file_digests = digests.is_some();
+
+	let mut report = VerifyReport {
+		md5,
+		file_digests,
+		files: names.len().min(data_info.len()),
+		corrupted: vec![],
+		outer_error: None,
+	};
+	for (path, (offset, size)) in names.into_iter().zip(data_info) {
+		let digest = digests.as_mut().and_then(|e| e.next());
+		let problem = match inner.get(offset..offset.saturating_add(size)) {
+			None => Some(FileProblem::OutOfBounds { offset, size }),
+			Some(data) if file_digests => {
+				let check = DigestCheck::compare(digest, &Sha1::from(data).digest().bytes());
+				(!check.is_valid()).then_some(FileProblem::Digest(check))
+			},
+			Some(_) => None,
+		};
+		if let Some(problem) = problem {
+			report.corrupted.push(CorruptedFile { path, problem });
+		}
+	}
+	Ok(report)
+}
+
+#[cfg(test)]
+mod test {
+	use std::{fs, path::PathBuf};
+
+	use sha1_smol::Sha1;
+
+	use crate::{
+		error::WtBlkError,
+		vromf::{
+			binary_container::encode_bin_vromf,
+			decode_inner_vromf,
+			encode_inner_vromf,
+			integrity::{verify, CorruptedFile, DigestCheck, FileProblem},
+			File,
+			HeaderType,
+			Metadata,
+			Packing,
+			PlatformType,
+		},
+	};
+
+	fn meta(packing: Packing) -> Metadata {
+		Metadata {
+			header_type: Some(HeaderType::VRFS),
+			platform:    Some(PlatformType::Pc),
+			packing:     Some(packing),
+			version:     None,
+		}
+	}
+
+	#[test]
+	fn intact() {
+		let report = verify(&fs::read("./samples/regional.vromfs.bin").unwrap()).unwrap();
+		assert!(report.is_valid(), "{report:?}");
+		assert!(report.files > 0);
+	}
+
+	#[test]
+	fn reports_every_mismatch() {
+		let inner = fs::read("./samples/checked.vromfs").unwrap();
+		let files = decode_inner_vromf(&inner, true).unwrap();
+		let mut encoded = encode_inner_vromf(&files, true).unwrap();
+
+		// Flip one byte in the data of the first two files, after the digests were computed
+		let tampered = [&files[0], &files[1]].map(|file| {
+			let offset = encoded
+				.windows(file.buf().len())
+				.rposition(|w| w == file.buf())
+				.unwrap();
+			encoded[offset] ^= 0xFF;
+			file.path().to_owned()
+		});
+
+		let vromf = encode_bin_vromf(&encoded, meta(Packing::ZSTD_OBFS)).unwrap();
+		let report = verify(&vromf).unwrap();
+		assert_eq!(report.md5, Some(DigestCheck::Valid));
+		assert!(report.file_digests);
+		assert_eq!(report.files, files.len());
+		assert_eq!(
+			report
+				.corrupted
+				.iter()
+				.map(|file| file.path.clone())
+				.collect::<Vec<_>>(),
+			tampered
+		);
+		let CorruptedFile {
+			problem: FileProblem::Digest(DigestCheck::Mismatch { expected, .. }),
+			..
+		} = &report.corrupted[0]
+		else {
+			panic!("{:?}", report.corrupted[0]);
+		};
+		assert_eq!(expected, &Sha1::from(files[0].buf()).digest().bytes());
+		assert!(!report.is_valid());
+
+		// Decoding stops at the first file, naming its digest
+		let Err(WtBlkError::DigestMismatch { expected, .. }) = decode_inner_vromf(&encoded, true)
+		else {
+			panic!("Tampered file was not detected");
+		};
+		assert_eq!(expected, Sha1::from(files[0].buf()).digest().to_string());
+	}
+
+	#[test]
+	fn md5_mismatch() {
+		let files = [File::from_raw(PathBuf::from("a.blk"), b"data".to_vec())];
+		let mut vromf = encode_bin_vromf(
+			&encode_inner_vromf(&files, false).unwrap(),
+			meta(Packing::ZSTD_OBFS),
+		)
+		.unwrap();
+		let last = vromf.len() - 1;
+		vromf[last] ^= 0xFF;
+
+		let report = verify(&vromf).unwrap();
+		assert!(matches!(report.md5, Some(DigestCheck::Mismatch { .. })));
+		assert!(!report.file_digests);
+		assert!(report.corrupted.is_empty());
+
+		vromf.truncate(last);
+		assert_eq!(verify(&vromf).unwrap().md5, Some(DigestCheck::Missing));
+	}
+
+	#[test]
+	fn truncated() {
+		let vromf = fs::read("./samples/regional.vromfs.bin").unwrap();
+		let report = verify(&vromf[..vromf.len() / 2]).unwrap();
+		assert_eq!(report.md5, None);
+		assert!(report.outer_error.is_some());
+		assert_eq!(report.files, 0);
+		assert!(!report.is_valid());
+	}
+}
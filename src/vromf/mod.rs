@@ -7,13 +7,35 @@ mod util;
 /// It is generally expected to directly call into the public interfaces from this module, ignoring the inner lower-level functions
 mod binary_container;
 
+/// Errors specific to the vromf container formats
+pub mod error;
 pub(crate) mod file;
 mod header;
 /// Unpacks the contents after binary unpacking
 mod inner_container;
+/// Digest verification reporting every corrupted file
+mod integrity;
+/// Glob based selection of files within a vromf
+mod path_filter;
+/// Minimal ustar writer, producing reproducible archives
+mod tar;
 #[cfg(test)]
 mod test;
 mod unpacker;
 
+pub use binary_container::{decode_bin_vromf, encode_bin_vromf};
+pub use enums::{HeaderType, Packing, PlatformType};
 pub use file::File;
-pub use unpacker::{BlkOutputFormat, VromfUnpacker};
+pub use header::Metadata;
+pub use inner_container::{decode_inner_vromf, encode_inner_vromf};
+pub use integrity::{verify, CorruptedFile, DigestCheck, FileProblem, VerifyReport};
+pub use path_filter::PathFilter;
+pub use unpacker::{
+	BlkOutputFormat,
+	ExtractOptions,
+	ExtractSummary,
+	TarFormat,
+	VromfEntry,
+	VromfEntryKind,
+	VromfUnpacker,
+};
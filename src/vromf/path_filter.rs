@@ -0,0 +1,87 @@
+use std::path::Path;
+
+/// Selects files by their path inside of a vromf, using glob patterns.
+/// `*` and `?` match within a single path component, `**` matches across components, `**/` also matches no directory at all.
+/// Patterns starting with `!` exclude paths, which takes precedence over including them.
+/// Without any including pattern, every path that is not excluded matches
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct PathFilter {
+	include: Vec<String>,
+	exclude: Vec<String>,
+}
+
+impl PathFilter {
+	pub fn new<S: AsRef<str>>(patterns: impl IntoIterator<Item = S>) -> Self {
+		let mut filter = Self::default();
+		for pattern in patterns {
+			match pattern.as_ref().strip_prefix('!') {
+				Some(exclude) => filter.exclude.push(exclude.to_owned()),
+				None => filter.include.push(pattern.as_ref().to_owned()),
+			}
+		}
+		filter
+	}
+
+	pub fn matches(&self, path: &Path) -> bool {
+		// Paths are compared with forward slashes, as they are stored in the vromf
+		let path = path.to_string_lossy().replace('\\', "/");
+		let matching = |pattern: &String| glob_match(pattern.as_bytes(), path.as_bytes());
+
+		(self.include.is_empty() || self.include.iter().any(matching))
+			&& !self.exclude.iter().any(matching)
+	}
+}
+
+fn glob_match(pattern: &[u8], path: &[u8]) -> bool {
+	match pattern {
+		[] => path.is_empty(),
+		// Any amount of directories, including none
+		[b'*', b'*', b'/', rest @ ..] => (0..=path.len())
+			.filter(|&i| i == 0 || path[i - 1] == b'/')
+			.any(|i| glob_match(rest, &path[i..])),
+		[b'*', b'*', rest @ ..] => (0..=path.len()).any(|i| glob_match(rest, &path[i..])),
+		[b'*', rest @ ..] => {
+			let component = path.iter().position(|&b| b == b'/').unwrap_or(path.len());
+			(0..=component).any(|i| glob_match(rest, &path[i..]))
+		},
+		[b'?', rest @ ..] => matches!(path, [c, ..] if *c != b'/') && glob_match(rest, &path[1..]),
+		[c, rest @ ..] => path.first() == Some(c) && glob_match(rest, &path[1..]),
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use std::path::Path;
+
+	use crate::vromf::path_filter::PathFilter;
+
+	#[test]
+	fn globs() {
+		let matches =
+			|patterns: &[&str], path: &str| PathFilter::new(patterns).matches(Path::new(path));
+
+		assert!(matches(
+			&["gameData/units/**.blk"],
+			"gameData/units/tankModels/t_34.blk"
+		));
+		assert!(matches(&["gameData/units/**.blk"], "gameData/units/a.blk"));
+		assert!(!matches(
+			&["gameData/units/**.blk"],
+			"gameData/units/a.blkx"
+		));
+		assert!(matches(&["config/*.blk"], "config/hud.blk"));
+		assert!(!matches(&["config/*.blk"], "config/hud/hud.blk"));
+		assert!(matches(&["**/nm"], "nm"));
+		assert!(matches(&["config/h?d.blk"], "config/hud.blk"));
+		assert!(!matches(&["config?hud.blk"], "config/hud.blk"));
+
+		assert!(matches(&[], "anything"));
+		assert!(matches(&["!*.dict"], "config/hud.blk"));
+		assert!(!matches(&["!*.dict"], "a.dict"));
+		assert!(!matches(
+			&["config/**", "!config/hud/**"],
+			"config/hud/hud.blk"
+		));
+		assert!(matches(&["config/**", "!config/hud/**"], "config/hud.blk"));
+	}
+}
@@ -0,0 +1,144 @@
+use std::{io, io::Write, path::Path};
+
+use crate::{error::WtBlkError, vromf::error::VromfError};
+
+const BLOCK_SIZE: usize = 512;
+/// Largest size that fits the 11 octal digits of the size field
+const MAX_FILE_SIZE: u64 = 0o77777777777;
+
+/// Writes regular files as ustar archive.
+/// Ownership, permissions and modification times are fixed, such that identical files produce identical archives
+pub struct TarWriter<W: Write> {
+	writer: W,
+}
+
+impl<W: Write> TarWriter<W> {
+	pub fn new(writer: W) -> Self {
+		Self { writer }
+	}
+
+	pub fn append(&mut self, path: &Path, buf: &[u8]) -> Result<(), WtBlkError> {
+		let header = header(path, buf.len())?;
+		self.writer.write_all(&header)?;
+		self.writer.write_all(buf)?;
+		self.pad(buf.len())?;
+		Ok(())
+	}
+
+	/// Writes the two empty blocks terminating an archive
+	pub fn finish(mut self) -> io::Result<W> {
+		self.writer.write_all(&[0; BLOCK_SIZE * 2])?;
+		Ok(self.writer)
+	}
+
+	fn pad(&mut self, len: usize) -> io::Result<()> {
+		let padding = len.next_multiple_of(BLOCK_SIZE) - len;
+		self.writer.write_all(&[0; BLOCK_SIZE][..padding])
+	}
+}
+
+fn header(path: &Path, size: usize) -> Result<[u8; BLOCK_SIZE], VromfError> {
+	let path_str = path.to_string_lossy().replace('\\', "/");
+	let (prefix, name) =
+		split_path(&path_str).ok_or_else(|| VromfError::UnarchivablePath(path.to_owned()))?;
+	if size as u64 > MAX_FILE_SIZE {
+		return Err(VromfError::UnarchivableSize {
+			path: path.to_owned(),
+			size,
+		});
+	}
+
+	let mut header = [0; BLOCK_SIZE];
+	header[0..name.len()].copy_from_slice(name.as_bytes());
+	write_octal(&mut header[100..108], 0o644);
+	write_octal(&mut header[108..116], 0);
+	write_octal(&mut header[116..124], 0);
+	write_octal(&mut header[124..136], size as u64);
+	write_octal(&mut header[136..148], 0);
+	header[156] = b'0';
+	header[257..263].copy_from_slice(b"ustar\0");
+	header[263..265].copy_from_slice(b"00");
+	header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+
+	// The checksum is computed with its own field set to spaces
+	header[148..156].fill(b' ');
+	let checksum = header.iter().map(|&b| b as u64).sum();
+	write_octal(&mut header[148..155], checksum);
+	Ok(header)
+}
+
+/// Splits a path into the 155 byte prefix and 100 byte name fields, at a separator
+fn split_path(path: &str) -> Option<(&str, &str)> {
+	if path.len() <= 100 {
+		return Some(("", path));
+	}
+	path.match_indices('/')
+		.map(|(i, _)| (&path[..i], &path[i + 1..]))
+		.find(|(prefix, name)| prefix.len() <= 155 && name.len() <= 100 && !name.is_empty())
+}
+
+/// Zero padded octal number, terminated by a null byte
+fn write_octal(field: &mut [u8], value: u64) {
+	let digits = field.len() - 1;
+	field[..digits].copy_from_slice(format!("{value:0digits$o}").as_bytes());
+	field[digits] = 0;
+}
+
+#[cfg(test)]
+mod test {
+	use std::path::Path;
+
+	use crate::vromf::{
+		error::VromfError,
+		tar::{header, TarWriter, BLOCK_SIZE},
+	};
+
+	#[test]
+	fn layout() {
+		let mut writer = TarWriter::new(vec![]);
+		writer.append(Path::new("config/hud.blk"), b"hud").unwrap();
+		let archive = writer.finish().unwrap();
+		assert_eq!(archive.len(), BLOCK_SIZE * 4);
+
+		let header = &archive[..BLOCK_SIZE];
+		assert!(header.starts_with(b"config/hud.blk\0"));
+		assert_eq!(&header[124..136], b"00000000003\0");
+		assert_eq!(&archive[BLOCK_SIZE..BLOCK_SIZE + 4], b"hud\0");
+
+		let checksum =
+			u64::from_str_radix(std::str::from_utf8(&header[148..154]).unwrap(), 8).unwrap();
+		let expected: u64 = header
+			.iter()
+			.enumerate()
+			.map(|(i, &b)| {
+				if (148..156).contains(&i) {
+					b' ' as u64
+				} else {
+					b as u64
+				}
+			})
+			.sum();
+		assert_eq!(checksum, expected);
+	}
+
+	#[test]
+	fn long_paths() {
+		let dir = "d".repeat(120);
+		let split = header(Path::new(&format!("{dir}/file.blk")), 0).unwrap();
+		assert!(split.starts_with(b"file.blk\0"));
+		assert!(split[345..].starts_with(dir.as_bytes()));
+
+		assert!(header(Path::new(&"f".repeat(101)), 0).is_err());
+	}
+
+	#[test]
+	fn large_files() {
+		let max = header(Path::new("file.blk"), 0o77777777777).unwrap();
+		assert_eq!(&max[124..136], b"77777777777\0");
+
+		assert!(matches!(
+			header(Path::new("file.blk"), 8 << 30),
+			Err(VromfError::UnarchivableSize { size, .. }) if size == 8 << 30
+		));
+	}
+}
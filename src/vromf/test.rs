@@ -1,32 +1,264 @@
-use std::{fs, path::PathBuf, str::FromStr};
+#[cfg(feature = "zip")]
+use std::io::{Cursor, Read};
+use std::{
+	fs,
+	path::{Path, PathBuf},
+	str::FromStr,
+	sync::Arc,
+};
 
+use sha1_smol::Sha1;
 use wt_version::Version;
+#[cfg(feature = "zip")]
+use zip::ZipArchive;
 
-use crate::vromf::{
-	binary_container::decode_bin_vromf,
-	inner_container::decode_inner_vromf,
-	unpacker::{BlkOutputFormat, VromfUnpacker, ZipFormat},
-	File,
+#[cfg(feature = "zip")]
+use crate::vromf::unpacker::ZipFormat;
+use crate::{
+	blk::{
+		interner::StringInterner,
+		plaintext_serialize::json_format::JsonFormat,
+		util::maybe_blk,
+	},
+	error::WtBlkError,
+	vromf::{
+		binary_container::{decode_bin_vromf, encode_bin_vromf},
+		error::VromfError,
+		inner_container::{decode_inner_vromf, encode_inner_vromf},
+		unpacker::{BlkOutputFormat, ExtractOptions, TarFormat, VromfEntryKind, VromfUnpacker},
+		File,
+		HeaderType,
+		Metadata,
+		Packing,
+		PlatformType,
+	},
 };
 
 #[test]
 fn grp_vromf() {
 	let out = VromfUnpacker::from_file(&File::new("./samples/grp_hdr.vromfs.bin").unwrap(), true)
 		.unwrap();
-	let unpacked = out.unpack_all(Some(BlkOutputFormat::Json), true).unwrap();
+	let unpacked = out
+		.unpack_all(Some(BlkOutputFormat::Json(JsonFormat::default())), true)
+		.unwrap();
 	assert_eq!(2322, unpacked.len())
 }
 
+#[test]
+fn memory_mapped() {
+	let sample = "./samples/regional.vromfs.bin";
+	// SAFETY: Samples are not modified during tests
+	let mapped = unsafe { VromfUnpacker::from_path(sample, true) }.unwrap();
+	let (decoded, _) = decode_bin_vromf(&fs::read(sample).unwrap(), true).unwrap();
+	let files = decode_inner_vromf(&decoded, true).unwrap();
+
+	assert!(mapped
+		.raw_files()
+		.eq(files.iter().map(|file| file.as_ref())));
+	let (path, buf) = files[0].as_ref();
+	assert_eq!(mapped.get_raw(path), Some(buf));
+
+	let read = VromfUnpacker::from_file(&File::new(sample).unwrap(), true).unwrap();
+	// Without a format, BLK files are returned as they are stored
+	assert!(read
+		.unpack_where(|_| true, None, false)
+		.unwrap()
+		.iter()
+		.map(File::as_ref)
+		.eq(files.iter().map(File::as_ref)));
+
+	let format = Some(BlkOutputFormat::Json(JsonFormat::default()));
+	let unpacked = mapped.unpack_all(format, true).unwrap();
+	assert!(unpacked.iter().zip(&files).all(|(unpacked, raw)| {
+		!maybe_blk(raw) || serde_json::from_slice::<serde_json::Value>(unpacked.buf()).is_ok()
+	}));
+	assert_eq!(
+		unpacked.iter().map(File::as_ref).collect::<Vec<_>>(),
+		read.unpack_all(format, true)
+			.unwrap()
+			.iter()
+			.map(File::as_ref)
+			.collect::<Vec<_>>()
+	);
+}
+
+#[test]
+fn unpack_matching() {
+	let out = VromfUnpacker::from_file(&File::new("./samples/regional.vromfs.bin").unwrap(), true)
+		.unwrap();
+	let blk = out
+		.unpack_matching(["**.blk", "!**/nm"], None, false)
+		.unwrap();
+	assert!(!blk.is_empty());
+	assert!(blk.len() < out.raw_files().count());
+	assert!(blk
+		.iter()
+		.all(|file| file.path().extension() == Some("blk".as_ref())));
+
+	let where_blk = out
+		.unpack_where(|path| path.extension() == Some("blk".as_ref()), None, false)
+		.unwrap();
+	assert_eq!(
+		blk.iter().map(File::as_ref).collect::<Vec<_>>(),
+		where_blk.iter().map(File::as_ref).collect::<Vec<_>>()
+	);
+}
+
+#[test]
+fn list_files() {
+	let out = VromfUnpacker::from_file(&File::new("./samples/regional.vromfs.bin").unwrap(), true)
+		.unwrap();
+	let entries = out.list_files();
+	assert_eq!(entries.len(), out.raw_files().count());
+	for entry in &entries {
+		let raw = out.get_raw(&entry.path).unwrap();
+		assert_eq!(entry.size, raw.len());
+		assert_eq!(entry.kind, VromfEntryKind::detect(&entry.path, raw));
+		if let Some(digest) = entry.digest {
+			assert_eq!(digest, Sha1::from(raw).digest().bytes());
+		}
+	}
+	assert!(entries
+		.iter()
+		.any(|entry| matches!(entry.kind, VromfEntryKind::Blk(_))));
+	assert!(entries.windows(2).all(|w| w[0].offset < w[1].offset));
+	assert!(out.get_raw(Path::new("does/not/exist")).is_none());
+}
+
+#[test]
+fn extract_to() {
+	let dir = std::env::temp_dir().join(format!("wt_blk_extract_{}", std::process::id()));
+	let out = VromfUnpacker::from_file(&File::new("./samples/regional.vromfs.bin").unwrap(), true)
+		.unwrap();
+	let options = ExtractOptions {
+		unpack_blk_into: Some(BlkOutputFormat::Json(JsonFormat::default())),
+		converted_extension: Some("blkx".to_owned()),
+		skip_unchanged: true,
+		..Default::default()
+	};
+
+	let first = out.extract_to(&dir, &options).unwrap();
+	assert_eq!(first.written, out.raw_files().count());
+	for entry in out.list_files() {
+		let mut path = dir.join(&entry.path);
+		if matches!(entry.kind, VromfEntryKind::Blk(_)) {
+			path.set_extension("blkx");
+		}
+		assert!(path.is_file(), "{}", path.display());
+	}
+
+	let second = out.extract_to(&dir, &options).unwrap();
+	assert_eq!(second.written, 0);
+	assert_eq!(second.unchanged, first.written);
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn extract_rejects_traversal() {
+	let files = [File::from_raw(
+		PathBuf::from("../escaped"),
+		b"data".to_vec(),
+	)];
+	let meta = Metadata {
+		header_type: Some(HeaderType::VRFS),
+		platform:    Some(PlatformType::Pc),
+		packing:     Some(Packing::ZSTD_OBFS),
+		version:     None,
+	};
+	let inner = encode_inner_vromf(&files, false).unwrap();
+	let vromf = File::from_raw(PathBuf::new(), encode_bin_vromf(&inner, meta).unwrap());
+
+	let dir = std::env::temp_dir().join(format!("wt_blk_traversal_{}", std::process::id()));
+	let out = VromfUnpacker::from_file(&vromf, true).unwrap();
+	assert!(matches!(
+		out.extract_to(dir.join("nested"), &ExtractOptions::default()),
+		Err(WtBlkError::Vromf(VromfError::UnsafePath(_)))
+	));
+	assert!(!dir.exists());
+}
+
+#[test]
+fn tar_is_reproducible() {
+	let tar = |format| {
+		VromfUnpacker::from_file(&File::new("./samples/regional.vromfs.bin").unwrap(), true)
+			.unwrap()
+			.unpack_all_to_tar(
+				format,
+				Some(BlkOutputFormat::Json(JsonFormat::default())),
+				true,
+			)
+			.unwrap()
+	};
+	let plain = tar(TarFormat::Uncompressed);
+	assert_eq!(plain, tar(TarFormat::Uncompressed));
+	assert_eq!(plain.len() % 512, 0);
+
+	let compressed = tar(TarFormat::Zstd(3));
+	assert_eq!(compressed, tar(TarFormat::Zstd(3)));
+	assert_eq!(zstd::decode_all(compressed.as_slice()).unwrap(), plain);
+}
+
+#[test]
+fn shared_interner() {
+	let interner = Arc::new(StringInterner::new());
+	let out = VromfUnpacker::from_file(&File::new("./samples/regional.vromfs.bin").unwrap(), true)
+		.unwrap()
+		.with_string_interner(interner.clone());
+	out.unpack_all(Some(BlkOutputFormat::Json(JsonFormat::default())), true)
+		.unwrap();
+
+	let stats = interner.stats();
+	assert!(stats.strings > 0);
+	assert!(stats.saved_allocations() > 0);
+	assert!(stats.interned_bytes() < stats.uninterned_bytes());
+}
+
+#[cfg(feature = "zip")]
 #[test]
 fn write_to_zip() {
 	let out =
 		VromfUnpacker::from_file(&File::new("./samples/aces.vromfs.bin").unwrap(), true).unwrap();
 	let unpacked = out
-		.unpack_all_to_zip(ZipFormat::Compressed(1), Some(BlkOutputFormat::Json), true)
+		.unpack_all_to_zip(
+			ZipFormat::Compressed(1),
+			Some(BlkOutputFormat::Json(JsonFormat::default())),
+			true,
+		)
 		.unwrap();
 	assert_eq!(27440515, unpacked.len())
 }
 
+#[cfg(feature = "zip")]
+#[test]
+fn stream_to_zip() {
+	let open = || {
+		VromfUnpacker::from_file(&File::new("./samples/regional.vromfs.bin").unwrap(), true)
+			.unwrap()
+	};
+	let format = Some(BlkOutputFormat::Json(JsonFormat::default()));
+	let mut expected = open().unpack_all(format, true).unwrap();
+	expected.sort_unstable_by(|a, b| a.path().cmp(b.path()));
+
+	let sink = open()
+		.unpack_all_to_zip_writer(Cursor::new(vec![]), ZipFormat::Compressed(1), format, true)
+		.unwrap();
+	let mut archive = ZipArchive::new(sink).unwrap();
+	let mut streamed = (0..archive.len())
+		.map(|i| {
+			let mut entry = archive.by_index(i).unwrap();
+			let mut buf = vec![];
+			entry.read_to_end(&mut buf).unwrap();
+			File::from_raw(PathBuf::from(entry.name()), buf)
+		})
+		.collect::<Vec<_>>();
+	streamed.sort_unstable_by(|a, b| a.path().cmp(b.path()));
+
+	assert_eq!(
+		expected.iter().map(File::as_ref).collect::<Vec<_>>(),
+		streamed.iter().map(File::as_ref).collect::<Vec<_>>()
+	);
+}
+
 #[test]
 fn regular_vromf() {
 	let out =
@@ -53,7 +285,9 @@ fn regional() {
 fn no_nm_vromf() {
 	let out = VromfUnpacker::from_file(&File::new("./samples/atlases.vromfs.bin").unwrap(), true)
 		.unwrap();
-	let unpacked = out.unpack_all(Some(BlkOutputFormat::Json), true).unwrap();
+	let unpacked = out
+		.unpack_all(Some(BlkOutputFormat::Json(JsonFormat::default())), true)
+		.unwrap();
 	assert_eq!(8924, unpacked.len())
 }
 
@@ -1,32 +1,50 @@
 use std::{
+	collections::HashMap,
 	ffi::OsStr,
 	fmt::{Debug, Formatter},
-	io::{Cursor, Write},
-	mem,
-	ops::Deref,
-	path::Path,
+	fs,
+	io,
+	io::Write,
+	ops::{Deref, Range},
+	path::{Component, Path, PathBuf},
 	str::FromStr,
 	sync::Arc,
 };
-
-use color_eyre::{
-	eyre::{eyre, ContextCompat},
-	Help,
-	Report,
+#[cfg(feature = "zip")]
+use std::{
+	io::{Cursor, Seek},
+	mem,
 };
-use rayon::iter::{IntoParallelIterator, ParallelIterator};
+#[cfg(all(feature = "zip", feature = "parallel"))]
+use std::{sync::mpsc::sync_channel, thread};
+
+use memmap2::Mmap;
+#[cfg(feature = "parallel")]
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 use wt_version::Version;
+#[cfg(feature = "zip")]
 use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
 use zstd::dict::DecoderDictionary;
 
 use crate::{
 	blk,
-	blk::{nm_file::NameMap, util::maybe_blk},
+	blk::{
+		binary_deserialize::parser::ParseOptions,
+		file::FileType,
+		interner::StringInterner,
+		nm_file::NameMap,
+		plaintext_serialize::json_format::{DuplicateKeys, JsonFormat},
+		util::maybe_blk,
+	},
+	error::WtBlkError,
 	vromf::{
 		binary_container::decode_bin_vromf,
+		error::VromfError,
 		header::Metadata,
-		inner_container::decode_inner_vromf,
+		inner_container::index_inner_vromf,
+		tar::TarWriter,
 		File,
+		PathFilter,
 	},
 };
 
@@ -50,116 +68,256 @@ impl<'a> Deref for DictWrapper<'a> {
 /// Unpacks vromf image into all internal files, optionally formatting binary BLK files
 #[derive(Debug)]
 pub struct VromfUnpacker<'a> {
-	files:    Vec<File>,
-	dict:     Option<Arc<DictWrapper<'a>>>,
-	nm:       Option<Arc<NameMap>>,
-	metadata: Metadata,
+	/// Decompressed inner container, which all entries point into
+	inner:         Vec<u8>,
+	entries:       Vec<Entry>,
+	/// Position of each path in `entries`
+	index:         HashMap<PathBuf, usize>,
+	dict:          Option<Arc<DictWrapper<'a>>>,
+	nm:            Option<Arc<NameMap>>,
+	metadata:      Metadata,
+	parse_options: ParseOptions,
 }
 
 /// Defines plaintext format should be exported to
 #[derive(Copy, Clone, Debug)]
 pub enum BlkOutputFormat {
-	Json,
+	Json(JsonFormat),
 	BlkText,
 }
 
+/// Single file of the inner container, copied out only once it is unpacked
+#[derive(Clone, Debug)]
+struct Entry {
+	path:   PathBuf,
+	range:  Range<usize>,
+	digest: Option<[u8; 20]>,
+}
+
+/// Describes a file stored in the vromf, without unpacking it
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct VromfEntry {
+	pub path:   PathBuf,
+	/// Size of the file as stored, before unpacking BLK files
+	pub size:   usize,
+	/// Offset of the file inside of the decompressed inner container
+	pub offset: usize,
+	/// SHA1 of the file, only present when the vromf has a digest
+	pub digest: Option<[u8; 20]>,
+	pub kind:   VromfEntryKind,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum VromfEntryKind {
+	/// Binary BLK file
+	Blk(FileType),
+	/// Shared name map of SLIM BLK files
+	NameMap,
+	/// ZSTD dictionary of SLIM_ZST_DICT BLK files
+	Dictionary,
+	Dxp,
+	Grp,
+	Other,
+}
+
+impl VromfEntryKind {
+	pub fn detect(path: &Path, buf: &[u8]) -> Self {
+		if path.file_name() == Some(OsStr::new("nm")) {
+			return Self::NameMap;
+		}
+		if path.extension() == Some(OsStr::new("dict")) {
+			return Self::Dictionary;
+		}
+		if path.extension() == Some(OsStr::new("blk")) {
+			if let Ok(file_type) = FileType::of_file(buf) {
+				return Self::Blk(file_type);
+			}
+		}
+		match buf.get(..4) {
+			Some(b"DxP2") => Self::Dxp,
+			Some(b"GRP2") => Self::Grp,
+			_ => Self::Other,
+		}
+	}
+}
+
+/// Configures [`VromfUnpacker::extract_to`]
+#[derive(Clone, Debug, Default)]
+pub struct ExtractOptions {
+	pub unpack_blk_into:     Option<BlkOutputFormat>,
+	pub apply_overrides:     bool,
+	/// Replaces the extension of converted BLK files, such as `blkx` for JSON output
+	pub converted_extension: Option<String>,
+	/// Leaves files untouched whose contents already match, keeping their modification time
+	pub skip_unchanged:      bool,
+}
+
+/// Amount of files [`VromfUnpacker::extract_to`] wrote or skipped
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct ExtractSummary {
+	pub written:   usize,
+	pub unchanged: usize,
+}
+
+#[cfg(feature = "zip")]
 #[derive(Copy, Clone, Debug)]
 pub enum ZipFormat {
 	Uncompressed,
 	Compressed(u8),
 }
 
+#[derive(Copy, Clone, Debug)]
+pub enum TarFormat {
+	Uncompressed,
+	/// ZSTD compressed with the given level, commonly known as tar.zst
+	Zstd(i32),
+}
+
 impl VromfUnpacker<'_> {
-	pub fn from_file(file: &File, validate: bool) -> Result<Self, Report> {
-		let (decoded, metadata) = decode_bin_vromf(file.buf(), validate)?;
-		let inner = decode_inner_vromf(&decoded, validate)?;
+	pub fn from_file(file: &File, validate: bool) -> Result<Self, WtBlkError> {
+		Self::from_buf(file.buf(), validate)
+	}
+
+	/// Memory-maps the vromf instead of reading it, such that only the decompressed container is held in memory
+	/// # Safety
+	/// The file must not be modified or truncated until this call returns, otherwise the mapping changes underneath it, which is UB
+	pub unsafe fn from_path(path: impl AsRef<Path>, validate: bool) -> Result<Self, WtBlkError> {
+		let file = fs::File::open(path)?;
+		// SAFETY: Upheld by the caller, the mapping is dropped before returning
+		let mmap = unsafe { Mmap::map(&file)? };
+		Self::from_buf(&mmap, validate)
+	}
 
-		let nm = inner
+	fn from_buf(file: &[u8], validate: bool) -> Result<Self, WtBlkError> {
+		let (inner, metadata) = decode_bin_vromf(file, validate)?;
+		let entries = index_inner_vromf(&inner, validate)?
+			.into_iter()
+			.map(|(path, range, digest)| Entry {
+				path,
+				range,
+				digest,
+			})
+			.collect::<Vec<_>>();
+		let index = entries
+			.iter()
+			.enumerate()
+			.map(|(i, entry)| (entry.path.clone(), i))
+			.collect();
+
+		let nm = entries
 			.iter()
-			.find(|elem| elem.path().file_name() == Some(OsStr::new("nm")))
-			.map(|elem| NameMap::from_encoded_file(&elem.buf()))
+			.find(|elem| elem.path.file_name() == Some(OsStr::new("nm")))
+			.map(|elem| NameMap::from_encoded_file(&inner[elem.range.clone()]))
 			.transpose()?
 			.map(|elem| Arc::new(elem));
 
-		let dict = inner
+		let dict = entries
 			.iter()
-			.find(|elem| elem.path().extension() == Some(OsStr::new("dict")))
-			.map(|elem| Arc::new(DictWrapper(DecoderDictionary::copy(&elem.buf()))));
+			.find(|elem| elem.path.extension() == Some(OsStr::new("dict")))
+			.map(|elem| {
+				Arc::new(DictWrapper(DecoderDictionary::copy(
+					&inner[elem.range.clone()],
+				)))
+			});
 
 		Ok(Self {
-			files: inner,
+			inner,
+			entries,
+			index,
 			dict,
 			nm,
 			metadata,
+			parse_options: ParseOptions::default(),
 		})
 	}
 
+	/// Sets the options BLK files are parsed with when unpacking them
+	pub fn with_parse_options(mut self, options: ParseOptions) -> Self {
+		self.parse_options = options;
+		self
+	}
+
+	/// Shares one allocation per distinct name and string across all BLK files this unpacker parses
+	pub fn with_string_interner(mut self, interner: Arc<StringInterner>) -> Self {
+		self.parse_options.interner = Some(interner);
+		self
+	}
+
+	/// Interner used for BLK files, whose statistics reflect all files unpacked so far
+	pub fn string_interner(&self) -> Option<&Arc<StringInterner>> {
+		self.parse_options.interner.as_ref()
+	}
+
 	pub fn unpack_all(
-		mut self,
+		self,
 		unpack_blk_into: Option<BlkOutputFormat>,
 		apply_overrides: bool,
-	) -> Result<Vec<File>, Report> {
-		// Important: We own self here, so "destroying" the files vector isn't an issue
-		// Due to partial moving rules this is necessary
-		let files = mem::replace(&mut self.files, vec![]);
-		files
-			.into_par_iter()
-			.panic_fuse()
-			.map(|file| self.unpack_file(file, unpack_blk_into, apply_overrides))
-			.collect::<Result<Vec<File>, Report>>()
+	) -> Result<Vec<File>, WtBlkError> {
+		self.unpack_where(|_| true, unpack_blk_into, apply_overrides)
+	}
+
+	/// Unpacks only the files matching any of the glob patterns, see [`PathFilter`] for their syntax
+	pub fn unpack_matching<S: AsRef<str>>(
+		&self,
+		patterns: impl IntoIterator<Item = S>,
+		unpack_blk_into: Option<BlkOutputFormat>,
+		apply_overrides: bool,
+	) -> Result<Vec<File>, WtBlkError> {
+		let filter = PathFilter::new(patterns);
+		self.unpack_where(
+			|path| filter.matches(path),
+			unpack_blk_into,
+			apply_overrides,
+		)
+	}
+
+	/// Unpacks only the files whose path fulfills the predicate, other files are not copied or decoded at all
+	pub fn unpack_where(
+		&self,
+		predicate: impl Fn(&Path) -> bool + Sync,
+		unpack_blk_into: Option<BlkOutputFormat>,
+		apply_overrides: bool,
+	) -> Result<Vec<File>, WtBlkError> {
+		self.try_map_entries(|entry| {
+			predicate(&entry.path)
+				.then(|| {
+					self.unpack_file(self.materialize(entry), unpack_blk_into, apply_overrides)
+				})
+				.transpose()
+		})
 	}
 
 	/// Skips the buffering step and directly writes the file to disk, using a provided writer
 	pub fn unpack_all_with_writer<W: Write>(
-		mut self,
+		self,
 		unpack_blk_into: Option<BlkOutputFormat>,
 		apply_overrides: bool,
-		writer: impl FnOnce(&mut File) -> Result<W, Report> + Sync + Send + Copy,
-	) -> Result<(), Report> {
-		// Important: We own self here, so "destroying" the files vector isn't an issue
-		// Due to partial moving rules this is necessary
-		let files = mem::replace(&mut self.files, vec![]);
-		files
-			.into_par_iter()
-			.panic_fuse()
-			.map(|mut file| {
-				let mut w = writer(&mut file)?;
-				self.unpack_file_with_writer(&mut file, unpack_blk_into, apply_overrides, &mut w)?;
-				Ok(())
-			})
-			.collect::<Result<(), Report>>()
+		writer: impl FnOnce(&mut File) -> Result<W, WtBlkError> + Sync + Send + Copy,
+	) -> Result<(), WtBlkError> {
+		self.try_map_entries(|entry| {
+			let mut file = self.materialize(entry);
+			let mut w = writer(&mut file)?;
+			self.unpack_file_with_writer(&mut file, unpack_blk_into, apply_overrides, &mut w)?;
+			Ok(Some(()))
+		})?;
+		Ok(())
 	}
 
+	#[cfg(feature = "zip")]
 	pub fn unpack_all_to_zip(
-		mut self,
+		self,
 		zip_format: ZipFormat,
 		unpack_blk_into: Option<BlkOutputFormat>,
 		apply_overrides: bool,
-	) -> Result<Vec<u8>, Report> {
-		// Important: We own self here, so "destroying" the files vector isn't an issue
-		// Due to partial moving rules this is necessary
-		let files = mem::replace(&mut self.files, Default::default());
-		let unpacked = files
-			.into_par_iter()
-			.panic_fuse()
-			.map(|file| self.unpack_file(file, unpack_blk_into, apply_overrides))
-			.collect::<Result<Vec<File>, Report>>()?;
+	) -> Result<Vec<u8>, WtBlkError> {
+		let unpacked = self.unpack_all(unpack_blk_into, apply_overrides)?;
 
 		let mut buf = Cursor::new(Vec::with_capacity(4096));
 		let mut writer = ZipWriter::new(&mut buf);
-
-		let (compression_level, compression_method) = match zip_format {
-			ZipFormat::Uncompressed => (0, CompressionMethod::STORE),
-			ZipFormat::Compressed(level) => (level, CompressionMethod::DEFLATE),
-		};
+		let options = zip_options(zip_format);
 
 		for f in unpacked.into_iter() {
-			writer.start_file(
-				f.path().to_string_lossy(),
-				SimpleFileOptions::default()
-					.compression_level(Some(compression_level as _))
-					.compression_method(compression_method),
-			)?;
+			writer.start_file(f.path().to_string_lossy(), options)?;
 			writer.write_all(f.buf())?;
 		}
 
@@ -167,23 +325,177 @@ impl VromfUnpacker<'_> {
 		Ok(buf.into_inner())
 	}
 
+	/// Streams the zip into `sink` while files are being unpacked, returning the sink once the archive is complete.
+	/// Only a few unpacked files are held in memory at once, and they are written in the order they finish unpacking
+	#[cfg(feature = "zip")]
+	pub fn unpack_all_to_zip_writer<W: Write + Seek>(
+		self,
+		sink: W,
+		zip_format: ZipFormat,
+		unpack_blk_into: Option<BlkOutputFormat>,
+		apply_overrides: bool,
+	) -> Result<W, WtBlkError> {
+		let options = zip_options(zip_format);
+
+		#[cfg(not(feature = "parallel"))]
+		return write_zip(
+			sink,
+			options,
+			self.entries.iter().map(|entry| {
+				self.unpack_file(self.materialize(entry), unpack_blk_into, apply_overrides)
+			}),
+		);
+
+		#[cfg(feature = "parallel")]
+		let (tx, rx) = sync_channel(rayon::current_num_threads() * 2);
+		#[cfg(feature = "parallel")]
+		thread::scope(|s| {
+			s.spawn(|| {
+				// Sending only fails once the receiver gave up on an error, which stops the remaining workers
+				let _ = self
+					.entries
+					.par_iter()
+					.panic_fuse()
+					.try_for_each_with(tx, |tx, entry| {
+						tx.send(self.unpack_file(
+							self.materialize(entry),
+							unpack_blk_into,
+							apply_overrides,
+						))
+					});
+			});
+
+			write_zip(sink, options, rx)
+		})
+	}
+
+	/// Writes all files into `dir`, recreating the directory tree of the vromf.
+	/// Nothing is written when any path would end up outside of `dir`
+	pub fn extract_to(
+		&self,
+		dir: impl AsRef<Path>,
+		options: &ExtractOptions,
+	) -> Result<ExtractSummary, WtBlkError> {
+		let dir = dir.as_ref();
+		if let Some(entry) = self
+			.entries
+			.iter()
+			.find(|entry| !is_relative_path(&entry.path))
+		{
+			return Err(VromfError::UnsafePath(entry.path.clone()).into());
+		}
+
+		let written = self
+			.try_map_entries(|entry| {
+				let mut file = self.materialize(entry);
+				let converted = options.unpack_blk_into.is_some() && maybe_blk(&file);
+				let mut buf = Vec::with_capacity(file.buf().len());
+				self.unpack_file_with_writer(
+					&mut file,
+					options.unpack_blk_into,
+					options.apply_overrides,
+					&mut buf,
+				)?;
+
+				let mut target = dir.join(&entry.path);
+				if let (true, Some(extension)) = (converted, &options.converted_extension) {
+					target.set_extension(extension);
+				}
+				write_if_changed(&target, &buf, options.skip_unchanged)
+					.map(Some)
+					.map_err(|e| in_file(&entry.path, e))
+			})?
+			.into_iter()
+			.filter(|&written| written)
+			.count();
+
+		Ok(ExtractSummary {
+			written,
+			unchanged: self.entries.len() - written,
+		})
+	}
+
+	/// Files are sorted by path and carry no timestamps, such that the same vromf always yields the same archive
+	pub fn unpack_all_to_tar(
+		self,
+		tar_format: TarFormat,
+		unpack_blk_into: Option<BlkOutputFormat>,
+		apply_overrides: bool,
+	) -> Result<Vec<u8>, WtBlkError> {
+		let mut unpacked = self.unpack_all(unpack_blk_into, apply_overrides)?;
+		unpacked.sort_unstable_by(|a, b| a.path().cmp(b.path()));
+
+		match tar_format {
+			TarFormat::Uncompressed => {
+				let mut writer = TarWriter::new(Vec::with_capacity(4096));
+				for f in &unpacked {
+					writer.append(f.path(), f.buf())?;
+				}
+				Ok(writer.finish()?)
+			},
+			TarFormat::Zstd(level) => {
+				let encoder = zstd::Encoder::new(Vec::with_capacity(4096), level)?;
+				let mut writer = TarWriter::new(encoder);
+				for f in &unpacked {
+					writer.append(f.path(), f.buf())?;
+				}
+				Ok(writer.finish()?.finish()?)
+			},
+		}
+	}
+
 	pub fn unpack_one(
 		&self,
 		path_name: &Path,
 		unpack_blk_into: Option<BlkOutputFormat>,
 		apply_overrides: bool,
-	) -> Result<File, Report> {
-		let file = self
-			.files
+	) -> Result<File, WtBlkError> {
+		let entry = self
+			.entry(path_name)
+			.ok_or_else(|| VromfError::FileNotFound(path_name.to_owned()))?;
+		self.unpack_file(self.materialize(entry), unpack_blk_into, apply_overrides)
+	}
+
+	/// Borrows the raw contents of a file, without copying or unpacking it
+	pub fn get_raw(&self, path_name: &Path) -> Option<&[u8]> {
+		self.entry(path_name)
+			.map(|entry| &self.inner[entry.range.clone()])
+	}
+
+	/// Borrows the path and raw contents of every file, in the order they are stored in
+	pub fn raw_files(&self) -> impl Iterator<Item = (&Path, &[u8])> {
+		self.entries
+			.iter()
+			.map(|entry| (entry.path.as_path(), &self.inner[entry.range.clone()]))
+	}
+
+	fn entry(&self, path_name: &Path) -> Option<&Entry> {
+		self.index.get(path_name).map(|&i| &self.entries[i])
+	}
+
+	/// Applies `f` to every entry, keeping the values it returns. Runs on the rayon thread-pool with the `parallel` feature
+	fn try_map_entries<T: Send>(
+		&self,
+		f: impl Fn(&Entry) -> Result<Option<T>, WtBlkError> + Sync + Send,
+	) -> Result<Vec<T>, WtBlkError> {
+		#[cfg(feature = "parallel")]
+		return self
+			.entries
+			.par_iter()
+			.panic_fuse()
+			.filter_map(|entry| f(entry).transpose())
+			.collect();
+		#[cfg(not(feature = "parallel"))]
+		return self
+			.entries
 			.iter()
-			.find(|e| e.path() == path_name)
-			.context(format!(
-				"File {} was not found in VROMF",
-				path_name.to_string_lossy()
-			))
-			.suggestion("Validate file-name and ensure it was typed correctly")?
-			.to_owned();
-		self.unpack_file(file, unpack_blk_into, apply_overrides)
+			.filter_map(|entry| f(entry).transpose())
+			.collect();
+	}
+
+	/// Copies an entry into its own buffer, as unpacking BLK files happens in place
+	fn materialize(&self, entry: &Entry) -> File {
+		File::from_raw(entry.path.clone(), self.inner[entry.range.clone()].to_vec())
 	}
 
 	pub fn unpack_file(
@@ -191,9 +503,10 @@ impl VromfUnpacker<'_> {
 		mut file: File,
 		unpack_blk_into: Option<BlkOutputFormat>,
 		apply_overrides: bool,
-	) -> Result<File, Report> {
-		let mut buf = Cursor::new(Vec::with_capacity(4096));
+	) -> Result<File, WtBlkError> {
+		let mut buf = Vec::with_capacity(file.buf().len());
 		self.unpack_file_with_writer(&mut file, unpack_blk_into, apply_overrides, &mut buf)?;
+		*file.buf_mut() = buf;
 		Ok(file)
 	}
 
@@ -203,27 +516,33 @@ impl VromfUnpacker<'_> {
 		unpack_blk_into: Option<BlkOutputFormat>,
 		apply_overrides: bool,
 		mut writer: impl Write,
-	) -> Result<(), Report> {
-		match () {
-			_ if maybe_blk(&file) => {
-				if let Some(format) = unpack_blk_into {
-					let mut parsed = blk::unpack_blk(file.buf_mut(), self.dict(), self.nm.clone())?;
-
-					match format {
-						BlkOutputFormat::BlkText => {
-							if apply_overrides {
-								parsed.apply_overrides();
-							}
-							writer.write_all(parsed.as_blk_text()?.as_bytes())?;
-						},
-						BlkOutputFormat::Json => {
+	) -> Result<(), WtBlkError> {
+		match unpack_blk_into {
+			Some(format) if maybe_blk(file) => {
+				let mut parsed = blk::unpack_blk_with_options(
+					file.buf_mut(),
+					self.dict(),
+					self.nm.clone(),
+					self.parse_options.clone(),
+				)
+				.map_err(|e| in_file(file.path(), e))?;
+
+				match format {
+					BlkOutputFormat::BlkText => {
+						if apply_overrides {
+							parsed.apply_overrides();
+						}
+						parsed.as_blk_text_streaming(&mut writer)?;
+					},
+					BlkOutputFormat::Json(json_format) => {
+						if json_format.duplicates == DuplicateKeys::Merge {
 							parsed.merge_fields();
-							if apply_overrides {
-								parsed.apply_overrides();
-							}
-							parsed.as_serde_json_streaming(&mut writer)?;
-						},
-					}
+						}
+						if apply_overrides {
+							parsed.apply_overrides();
+						}
+						parsed.as_serde_json_streaming_with(&mut writer, &json_format)?;
+					},
 				}
 			},
 			// Default to the raw file
@@ -235,7 +554,7 @@ impl VromfUnpacker<'_> {
 		Ok(())
 	}
 
-	pub fn query_versions(&self) -> Result<Vec<Version>, Report> {
+	pub fn query_versions(&self) -> Result<Vec<Version>, WtBlkError> {
 		let mut versions = vec![];
 		if let Some(meta) = self.metadata.version {
 			versions.push(meta);
@@ -246,27 +565,91 @@ impl VromfUnpacker<'_> {
 			.map(|e| e.split())
 		{
 			let s = String::from_utf8(version_file)?;
-			versions.push(
-				Version::from_str(&s).map_err(|_| eyre!("Invalid version file contents: {s}"))?,
-			);
+			versions.push(Version::from_str(&s).map_err(|_| VromfError::InvalidVersion(s))?);
 		}
 
 		Ok(versions)
 	}
 
-	pub fn latest_version(&self) -> Result<Option<Version>, Report> {
+	pub fn latest_version(&self) -> Result<Option<Version>, WtBlkError> {
 		let mut versions = self.query_versions()?;
 		versions.sort_unstable();
 		Ok(versions.last().map(|e| e.to_owned()))
 	}
 
-	pub fn list_files(&self) {
-		for f in &self.files {
-			println!("{}", f.path().to_string_lossy());
-		}
+	/// Describes every file, in the order they are stored in
+	pub fn list_files(&self) -> Vec<VromfEntry> {
+		self.entries
+			.iter()
+			.map(|entry| {
+				let buf = &self.inner[entry.range.clone()];
+				VromfEntry {
+					path:   entry.path.clone(),
+					size:   buf.len(),
+					offset: entry.range.start,
+					digest: entry.digest,
+					kind:   VromfEntryKind::detect(&entry.path, buf),
+				}
+			})
+			.collect()
 	}
 
 	pub fn dict(&self) -> Option<&DecoderDictionary<'_>> {
 		self.dict.as_deref().map(Deref::deref)
 	}
 }
+
+#[cfg(feature = "zip")]
+fn write_zip<W: Write + Seek>(
+	sink: W,
+	options: SimpleFileOptions,
+	files: impl IntoIterator<Item = Result<File, WtBlkError>>,
+) -> Result<W, WtBlkError> {
+	let mut writer = ZipWriter::new(sink);
+	for f in files {
+		let f = f?;
+		writer.start_file(f.path().to_string_lossy(), options)?;
+		writer.write_all(f.buf())?;
+	}
+	Ok(writer.finish()?)
+}
+
+#[cfg(feature = "zip")]
+fn zip_options(zip_format: ZipFormat) -> SimpleFileOptions {
+	let (compression_level, compression_method) = match zip_format {
+		ZipFormat::Uncompressed => (0, CompressionMethod::STORE),
+		ZipFormat::Compressed(level) => (level, CompressionMethod::DEFLATE),
+	};
+	SimpleFileOptions::default()
+		.compression_level(Some(compression_level as _))
+		.compression_method(compression_method)
+}
+
+/// Entry names may only descend into the output directory
+fn is_relative_path(path: &Path) -> bool {
+	path.components()
+		.all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+/// Returns whether the file was written, which it is not when `skip_unchanged` is set and the contents already match
+fn write_if_changed(target: &Path, buf: &[u8], skip_unchanged: bool) -> io::Result<bool> {
+	if skip_unchanged
+		&& fs::metadata(target).is_ok_and(|meta| meta.len() == buf.len() as u64)
+		&& fs::read(target)? == buf
+	{
+		return Ok(false);
+	}
+	if let Some(parent) = target.parent() {
+		fs::create_dir_all(parent)?;
+	}
+	fs::write(target, buf)?;
+	Ok(true)
+}
+
+/// Attaches the path of the file inside of the vromf to an error
+fn in_file(path: &Path, e: impl Into<WtBlkError>) -> WtBlkError {
+	WtBlkError::InFile {
+		path:   PathBuf::from(path),
+		source: Box::new(e.into()),
+	}
+}